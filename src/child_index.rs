@@ -0,0 +1,149 @@
+//! Indices of the children of a [`RelRc`] object by edge value.
+//!
+//! See [`InnerData::index_children_by`].
+//!
+//! [`RelRc`]: crate::RelRc
+//! [`InnerData::index_children_by`]: crate::node::InnerData::index_children_by
+
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::Hash;
+
+use fxhash::FxHashMap;
+use thiserror::Error;
+
+/// The key of an outgoing edge: the creation order of the edge target and the
+/// position of the edge in the target's incoming edges.
+pub(crate) type OutgoingKey = (u64, usize);
+
+/// Error type for child lookups on objects whose children are not indexed by
+/// keys of the requested type, see
+/// [`InnerData::child_by_edge`](crate::node::InnerData::child_by_edge).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("The children of the object are not indexed by keys of this type")]
+pub struct NotIndexed;
+
+/// An index of the outgoing edges of an object, by edge value.
+pub(crate) trait ChildIndex<E> {
+    /// Add an outgoing edge with the given value to the index.
+    fn insert(&mut self, value: &E, key: OutgoingKey);
+
+    /// Remove an outgoing edge with the given value from the index.
+    fn remove(&mut self, value: &E, key: OutgoingKey);
+
+    /// Downcast the index to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// An index of outgoing edges by a key computed from their edge values.
+pub(crate) struct KeyedChildIndex<K, E> {
+    key_fn: Box<dyn Fn(&E) -> K>,
+    /// The outgoing edges for every key, in creation order of their targets.
+    children: FxHashMap<K, BTreeSet<OutgoingKey>>,
+}
+
+impl<K: Hash + Eq, E> KeyedChildIndex<K, E> {
+    pub(crate) fn new(key_fn: impl Fn(&E) -> K + 'static) -> Self {
+        Self {
+            key_fn: Box::new(key_fn),
+            children: FxHashMap::default(),
+        }
+    }
+
+    /// The outgoing edges with the given key, in creation order of their
+    /// targets.
+    pub(crate) fn get(&self, key: &K) -> impl Iterator<Item = OutgoingKey> + '_ {
+        self.children.get(key).into_iter().flatten().copied()
+    }
+}
+
+impl<K: Hash + Eq + 'static, E: 'static> ChildIndex<E> for KeyedChildIndex<K, E> {
+    fn insert(&mut self, value: &E, key: OutgoingKey) {
+        self.children
+            .entry((self.key_fn)(value))
+            .or_default()
+            .insert(key);
+    }
+
+    fn remove(&mut self, value: &E, key: OutgoingKey) {
+        let index_key = (self.key_fn)(value);
+        let Some(edges) = self.children.get_mut(&index_key) else {
+            return;
+        };
+        edges.remove(&key);
+        if edges.is_empty() {
+            self.children.remove(&index_key);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The child index of an object, if there is one.
+///
+/// Indices belong to an object and are not cloned along with it.
+pub(crate) struct ChildIndexSlot<E>(pub(crate) Option<Box<dyn ChildIndex<E>>>);
+
+impl<E> Default for ChildIndexSlot<E> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<E> Clone for ChildIndexSlot<E> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<E> fmt::Debug for ChildIndexSlot<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ChildIndexSlot")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use crate::RelRc;
+
+    use super::*;
+
+    #[test]
+    fn test_child_by_edge() {
+        let root = RelRc::<_, (&str, usize)>::new("root");
+        let early = RelRc::with_parents("early", [(root.clone(), ("op", 1))]);
+        root.index_children_by(|&(op, _)| op.to_string());
+
+        let late = RelRc::with_parents("late", [(root.clone(), ("op", 2))]);
+        let other = RelRc::with_parents("other", [(root.clone(), ("other_op", 3))]);
+        let child = |key: &str| {
+            root.child_by_edge(&key.to_string())
+                .unwrap()
+                .map(|e| *e.target().value())
+        };
+        assert_eq!(child("op"), Some("early"));
+        assert_eq!(child("other_op"), Some("other"));
+        assert_eq!(child("missing"), None);
+        // Lookups with another key type, or without an index, are errors
+        assert_eq!(root.child_by_edge(&"op").unwrap_err(), NotIndexed);
+        assert_eq!(
+            early.child_by_edge(&"op".to_string()).unwrap_err(),
+            NotIndexed
+        );
+
+        // The index is updated as children are dropped
+        drop(early);
+        assert_eq!(child("op"), Some("late"));
+        drop((late, other));
+        assert_eq!(child("op"), None);
+        assert_eq!(child("other_op"), None);
+        assert!(root.all_children().collect_vec().is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+//! Lowest common ancestors of [`RelRc`] objects and of [`HistoryGraph`] nodes.
+//!
+//! The common ancestors are found by walking the ancestors of all inputs at
+//! once, by decreasing generation, and recording which inputs reach every
+//! visited node. As generations strictly decrease along edges, every node is
+//! visited after all of its descendants, and the walk stops as soon as the
+//! remaining nodes are all ancestors of common ancestors.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+use fxhash::FxHashMap;
+use itertools::Itertools;
+
+use crate::registry::KeyStrategy;
+use crate::{ByCreation, HistoryGraph, RelRc};
+
+/// The inputs that reach a node, as a bit set, and whether the node is an
+/// ancestor of a common ancestor.
+struct Reached {
+    inputs: Vec<u64>,
+    stale: bool,
+}
+
+/// The lowest common ancestors of `starts`, by decreasing generation.
+///
+/// Nodes of equal generation are returned in decreasing order of `T`.
+fn lowest_common_ancestors<T, I>(
+    starts: impl IntoIterator<Item = T>,
+    generation: impl Fn(&T) -> usize,
+    parents: impl Fn(&T) -> I,
+) -> Vec<T>
+where
+    T: Clone + Ord + Hash,
+    I: IntoIterator<Item = T>,
+{
+    let starts = starts.into_iter().collect_vec();
+    let mut all_inputs = vec![0; starts.len().div_ceil(64)];
+    let mut reached: FxHashMap<T, Reached> = FxHashMap::default();
+    let mut queue = BinaryHeap::new();
+    for (i, start) in starts.into_iter().enumerate() {
+        all_inputs[i / 64] |= 1 << (i % 64);
+        reached
+            .entry(start.clone())
+            .or_insert_with(|| {
+                queue.push((generation(&start), start));
+                Reached {
+                    inputs: vec![0; all_inputs.len()],
+                    stale: false,
+                }
+            })
+            .inputs[i / 64] |= 1 << (i % 64);
+    }
+
+    // The number of queued nodes that are not stale
+    let mut n_active = queue.len();
+    let mut lcas = Vec::new();
+    while n_active > 0 {
+        let (_, node) = queue.pop().expect("active nodes are queued");
+        let Reached { inputs, stale } = &reached[&node];
+        let (inputs, mut stale) = (inputs.clone(), *stale);
+        if !stale {
+            n_active -= 1;
+            if inputs == all_inputs {
+                lcas.push(node.clone());
+                stale = true;
+            }
+        }
+        for parent in parents(&node) {
+            match reached.entry(parent) {
+                Entry::Occupied(mut entry) => {
+                    let parent = entry.get_mut();
+                    for (word, &new) in parent.inputs.iter_mut().zip(&inputs) {
+                        *word |= new;
+                    }
+                    if stale && !parent.stale {
+                        parent.stale = true;
+                        n_active -= 1;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    queue.push((generation(entry.key()), entry.key().clone()));
+                    n_active += usize::from(!stale);
+                    entry.insert(Reached {
+                        inputs: inputs.clone(),
+                        stale,
+                    });
+                }
+            }
+        }
+    }
+    lcas
+}
+
+impl<N, E> RelRc<N, E> {
+    /// The lowest common ancestors of the given objects.
+    ///
+    /// The common ancestors are the objects that are ancestors of all given
+    /// objects, including the objects themselves. The lowest common ancestors
+    /// are the common ancestors that are not ancestors of another common
+    /// ancestor. They are returned by decreasing generation, then decreasing
+    /// creation order. Only strong edges are followed.
+    pub fn lowest_common_ancestors(nodes: &[impl Borrow<Self>]) -> Vec<Self> {
+        let starts = nodes.iter().map(|node| ByCreation::from(node.borrow()));
+        lowest_common_ancestors(
+            starts,
+            |node| node.generation(),
+            |node| node.into_inner().all_parents().map(ByCreation::from),
+        )
+        .into_iter()
+        .map(|node| node.into_inner().clone())
+        .collect()
+    }
+
+    /// The best common ancestor of two objects, to use as the base of a
+    /// merge.
+    ///
+    /// This is the first of the [`RelRc::lowest_common_ancestors`] of the
+    /// objects, i.e. the one with the highest generation. Returns `None` if
+    /// the objects have no common ancestor.
+    pub fn merge_base(&self, other: &Self) -> Option<Self> {
+        Self::lowest_common_ancestors(&[self, other])
+            .into_iter()
+            .next()
+    }
+}
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// The lowest common ancestors in the graph of the given nodes.
+    ///
+    /// The common ancestors are the nodes of the graph that are ancestors of
+    /// all given nodes in the graph, including the nodes themselves. The
+    /// lowest common ancestors are the common ancestors that are not
+    /// ancestors of another common ancestor. They are returned by decreasing
+    /// generation, then decreasing key.
+    ///
+    /// Returns no nodes if any of the given nodes is not in the graph.
+    pub fn lowest_common_ancestors(&self, node_ids: &[S::Key]) -> Vec<S::Key> {
+        if !node_ids.iter().all(|&id| self.contains_id(id)) {
+            return Vec::new();
+        }
+        lowest_common_ancestors(
+            node_ids.iter().copied(),
+            |&id| self.generation(id).expect("node in graph"),
+            |&id| self.parents(id),
+        )
+    }
+
+    /// The best common ancestor in the graph of two nodes, to use as the base
+    /// of a merge.
+    ///
+    /// This is the first of the [`HistoryGraph::lowest_common_ancestors`] of
+    /// the nodes, i.e. the one with the highest generation. Returns `None` if
+    /// the nodes have no common ancestor in the graph.
+    pub fn merge_base(&self, a: S::Key, b: S::Key) -> Option<S::Key> {
+        self.lowest_common_ancestors(&[a, b]).into_iter().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lowest_common_ancestors() {
+        // A criss-cross history, with two lowest common ancestors
+        let root = RelRc::<_, ()>::new("root");
+        let a = RelRc::with_parents("a", [(root.clone(), ())]);
+        let b = RelRc::with_parents("b", [(root.clone(), ())]);
+        let m1 = RelRc::with_parents("m1", [(a.clone(), ()), (b.clone(), ())]);
+        let m2 = RelRc::with_parents("m2", [(b.clone(), ()), (a.clone(), ())]);
+        let x = RelRc::with_parents("x", [(m1.clone(), ())]);
+        let y = RelRc::with_parents("y", [(m2.clone(), ())]);
+
+        let values =
+            |nodes: Vec<RelRc<&'static str, ()>>| nodes.iter().map(|n| *n.value()).collect_vec();
+        assert_eq!(
+            values(RelRc::lowest_common_ancestors(&[&x, &y])),
+            ["b", "a"]
+        );
+        assert_eq!(
+            values(RelRc::lowest_common_ancestors(&[&x, &m1, &a])),
+            ["a"]
+        );
+        assert_eq!(values(RelRc::lowest_common_ancestors(&[&y])), ["y"]);
+        assert_eq!(x.merge_base(&m1).map(|n| *n.value()), Some("m1"));
+        assert!(x.merge_base(&RelRc::new("other")).is_none());
+
+        let mut graph = HistoryGraph::default();
+        let x_id = graph.insert_node(x);
+        graph.insert_node(m1);
+        let m2_id = graph.insert_node(m2);
+        let a_id = graph.insert_node(a);
+        let root_id = graph.insert_node(root);
+        // `b` and `y` are not in the graph: `a` is the only common ancestor
+        assert_eq!(graph.lowest_common_ancestors(&[x_id, m2_id]), [a_id]);
+        assert_eq!(graph.merge_base(a_id, root_id), Some(root_id));
+
+        let y_id = RelRc::register_in(&y, graph.registry());
+        assert!(graph.lowest_common_ancestors(&[x_id, y_id]).is_empty());
+    }
+}
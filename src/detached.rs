@@ -168,7 +168,10 @@ impl<'a, N, E> From<&'a InnerData<N, E>> for DetachedInnerData<&'a N, &'a E> {
             incoming: value
                 .all_incoming()
                 .iter()
-                .map(|e| (e.source().hash_id(), e.value()))
+                .map(|e| {
+                    let source = e.source().expect("detached edges are strong");
+                    (source.hash_id(), e.value())
+                })
                 .collect(),
         }
     }
@@ -1,9 +1,16 @@
 //! Parent-child relationships between [`RelRc`] objects.
+//!
+//! The edges between thread-safe [`RelArc`](crate::RelArc) objects are the
+//! same types, with the [`Shared`](crate::pointer::Shared) pointer kind, see
+//! [`crate::sync::edge`].
 
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 
+use derive_more::From;
 use derive_where::derive_where;
 
+use crate::pointer::{Local, PointerKind};
 use crate::{RelRc, RelWeak};
 
 /// A parent-child relationship between two [`RelRc`] objects.
@@ -17,21 +24,24 @@ use crate::{RelRc, RelWeak};
 /// panic. This also means that [`InnerEdgeData`] cannot be cloned.
 #[derive(Debug)]
 #[derive_where(Clone; E)]
-pub struct InnerEdgeData<N, E> {
+pub struct InnerEdgeData<N, E, P: PointerKind = Local> {
     /// The value of the edge.
     pub(crate) value: E,
-    /// The source (parent) of the edge (strong reference).
-    pub(crate) source: RelRc<N, E>,
+    /// The source (parent) of the edge.
+    ///
+    /// Strong edges keep their source alive; weak edges do not, and become
+    /// dangling once the source has been dropped.
+    pub(crate) source: P::Source<N, E>,
     /// The target (child) of the edge.
     ///
     /// This is a weak reference to avoid reference loops between the edge and
     /// the target node. However, the target is always the owner of the edge,
     /// so this reference can always be upgraded.
-    pub(crate) target: RelWeak<N, E>,
+    pub(crate) target: P::Weak<N, E>,
 }
 
-impl<N, E> InnerEdgeData<N, E> {
-    pub(crate) fn new(value: E, source: RelRc<N, E>, target: RelWeak<N, E>) -> Self {
+impl<N, E, P: PointerKind> InnerEdgeData<N, E, P> {
+    pub(crate) fn new(value: E, source: P::Source<N, E>, target: P::Weak<N, E>) -> Self {
         Self {
             value,
             source,
@@ -44,28 +54,107 @@ impl<N, E> InnerEdgeData<N, E> {
         &self.value
     }
 
-    /// The source node of the edge.
-    pub fn source(&self) -> &RelRc<N, E> {
-        &self.source
-    }
-
     /// Downgrade the edge to a [`WeakEdge`].
     ///
     /// Requires the position of the edge in the target's incoming edges.
-    pub(crate) fn downgrade(&self, target_pos: usize) -> WeakEdge<N, E> {
+    pub(crate) fn downgrade(&self, target_pos: usize) -> WeakEdge<N, E, P> {
         WeakEdge::new(target_pos, self.target.clone())
     }
-}
 
-impl<N, E> InnerEdgeData<N, E> {
     /// The target node of the edge.
     ///
     /// This upgrades the target node and returns a strong reference. It panics
     /// if the target node is no longer alive.
-    pub fn target(&self) -> RelRc<N, E> {
-        self.target
-            .upgrade()
-            .expect("target node is no longer alive")
+    pub fn target(&self) -> P::Node<N, E> {
+        P::upgrade(&self.target).expect("target node is no longer alive")
+    }
+}
+
+impl<N, E> InnerEdgeData<N, E> {
+    /// The source node of the edge, if the edge is strong.
+    ///
+    /// Use [`InnerEdgeData::upgrade_source`] to get the source of any edge
+    /// that is still alive.
+    pub fn source(&self) -> Option<&RelRc<N, E>> {
+        self.source.as_strong()
+    }
+
+    /// The source node of the edge, if it is still alive.
+    ///
+    /// Always succeeds for strong edges.
+    pub fn upgrade_source(&self) -> Option<RelRc<N, E>> {
+        self.source.upgrade()
+    }
+
+    /// The source node of the edge as a weak reference.
+    pub fn source_weak(&self) -> RelWeak<N, E> {
+        self.source.downgrade()
+    }
+
+    /// Whether the edge is weak, i.e. does not keep its source alive.
+    pub fn is_weak(&self) -> bool {
+        self.source.is_weak()
+    }
+
+    /// Whether the edge is weak and its source has been dropped.
+    pub fn is_dangling(&self) -> bool {
+        match &self.source {
+            EdgeSource::Strong(_) => false,
+            EdgeSource::Weak(weak) => weak.strong_count() == 0,
+        }
+    }
+}
+
+/// The source of an edge, owned or not by the edge.
+///
+/// Used to create nodes with weak incoming edges, see [`RelRc::with_sources`].
+#[derive(Debug, From)]
+#[derive_where(Clone)]
+pub enum EdgeSource<N, E> {
+    /// The edge keeps its source alive.
+    Strong(RelRc<N, E>),
+    /// The edge does not keep its source alive.
+    ///
+    /// Once the source has been dropped, the edge is dangling.
+    Weak(RelWeak<N, E>),
+}
+
+impl<N, E> EdgeSource<N, E> {
+    /// The source node, if it is strongly referenced.
+    pub fn as_strong(&self) -> Option<&RelRc<N, E>> {
+        match self {
+            EdgeSource::Strong(node) => Some(node),
+            EdgeSource::Weak(_) => None,
+        }
+    }
+
+    /// Convert into the source node, if it is strongly referenced.
+    pub fn into_strong(self) -> Option<RelRc<N, E>> {
+        match self {
+            EdgeSource::Strong(node) => Some(node),
+            EdgeSource::Weak(_) => None,
+        }
+    }
+
+    /// The source node, if it is still alive.
+    pub fn upgrade(&self) -> Option<RelRc<N, E>> {
+        match self {
+            EdgeSource::Strong(node) => Some(node.clone()),
+            EdgeSource::Weak(weak) => weak.upgrade(),
+        }
+    }
+
+    /// The source node as a weak reference.
+    pub fn downgrade(&self) -> RelWeak<N, E> {
+        match self {
+            EdgeSource::Strong(node) => node.downgrade(),
+            EdgeSource::Weak(weak) => weak.clone(),
+        }
+    }
+
+    /// Whether the source is weakly referenced.
+    pub fn is_weak(&self) -> bool {
+        matches!(self, EdgeSource::Weak(_))
     }
 }
 
@@ -82,26 +171,35 @@ impl<N, E> InnerEdgeData<N, E> {
 /// Upgrades to [`Edge`] if the reference is valid.
 #[derive(Debug)]
 #[derive_where(Clone)]
-pub struct WeakEdge<N, E> {
+pub struct WeakEdge<N, E, P: PointerKind = Local> {
     /// The index of the edge in the owner node's incoming edges.
     pub(crate) index: usize,
     /// The target node (and owner) of the edge.
-    pub(crate) target: RelWeak<N, E>,
+    pub(crate) target: P::Weak<N, E>,
 }
 
-impl<N, E> WeakEdge<N, E> {
-    pub(crate) fn new(index: usize, target: RelWeak<N, E>) -> Self {
+impl<N, E, P: PointerKind> WeakEdge<N, E, P> {
+    pub(crate) fn new(index: usize, target: P::Weak<N, E>) -> Self {
         Self { index, target }
     }
 
     /// Check if two weak references point to the same underlying data
     pub fn ptr_eq(&self, other: &Self) -> bool {
-        self.index == other.index && RelWeak::ptr_eq(&self.target, &other.target)
+        self.index == other.index && P::weak_addr(&self.target) == P::weak_addr(&other.target)
     }
     /// The edge target as a weak reference.
-    pub fn target(&self) -> &RelWeak<N, E> {
+    pub fn target(&self) -> &P::Weak<N, E> {
         &self.target
     }
+
+    /// Upgrades to a [`Edge`] if the reference is still valid.
+    pub fn upgrade(&self) -> Option<Edge<N, E, P>> {
+        let target = P::upgrade(&self.target)?;
+        Some(Edge {
+            index: self.index,
+            target,
+        })
+    }
 }
 
 /// Strong reference to an edge.
@@ -110,43 +208,65 @@ impl<N, E> WeakEdge<N, E> {
 /// this reference is in scope.
 #[derive(Debug)]
 #[derive_where(Clone)]
-pub struct Edge<N, E> {
+pub struct Edge<N, E, P: PointerKind = Local> {
     /// The index of the edge in the owner node's incoming edges.
     pub(crate) index: usize,
     /// The target node (and owner) of the edge.
-    pub(crate) target: RelRc<N, E>,
+    pub(crate) target: P::Node<N, E>,
 }
 
-impl<N, E> Edge<N, E> {
+impl<N, E, P: PointerKind> Edge<N, E, P> {
     /// The target node of the edge.
     ///
-    /// This is equivalent to derefencing `self` into a [`EdgeData`] and calling
-    /// [`EdgeData::target`], but avoids creating a new reference.
-    pub fn target(&self) -> &RelRc<N, E> {
+    /// This is equivalent to derefencing `self` into a [`InnerEdgeData`] and
+    /// calling [`InnerEdgeData::target`], but avoids creating a new reference.
+    pub fn target(&self) -> &P::Node<N, E> {
         &self.target
     }
 
     /// Consume the edge and return the target object.
-    pub fn into_target(self) -> RelRc<N, E> {
+    pub fn into_target(self) -> P::Node<N, E> {
         self.target
     }
 }
 
-impl<N, E> Deref for Edge<N, E> {
-    type Target = InnerEdgeData<N, E>;
+impl<N, E, P: PointerKind> Deref for Edge<N, E, P> {
+    type Target = InnerEdgeData<N, E, P>;
 
     fn deref(&self) -> &Self::Target {
-        self.target.incoming(self.index).unwrap()
+        &P::incoming(&self.target)[self.index]
     }
 }
 
-impl<N, E> WeakEdge<N, E> {
-    /// Upgrades to a [`Edge`] if the reference is still valid.
-    pub fn upgrade(&self) -> Option<Edge<N, E>> {
-        let target = self.target.upgrade()?;
-        Some(Edge {
-            index: self.index,
-            target,
-        })
+/// Edges are compared by identity: two references are equal if they refer to
+/// the same incoming edge of the same target node.
+impl<N, E, P: PointerKind> PartialEq for WeakEdge<N, E, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl<N, E, P: PointerKind> Eq for WeakEdge<N, E, P> {}
+
+impl<N, E, P: PointerKind> Hash for WeakEdge<N, E, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        P::weak_addr(&self.target).hash(state);
+    }
+}
+
+/// Edges are compared by identity, consistently with [`WeakEdge`].
+impl<N, E, P: PointerKind> PartialEq for Edge<N, E, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && P::node_addr(&self.target) == P::node_addr(&other.target)
+    }
+}
+
+impl<N, E, P: PointerKind> Eq for Edge<N, E, P> {}
+
+impl<N, E, P: PointerKind> Hash for Edge<N, E, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        P::node_addr(&self.target).hash(state);
     }
 }
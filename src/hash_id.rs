@@ -0,0 +1,300 @@
+//! Content-addressed identifiers for [`RelRc`] objects.
+//!
+//! A [`RelRcHash`] is a Merkle hash over the value of an object, the values of
+//! its incoming edges and the hashes of its parents. Two objects with the same
+//! hash thus have the same value and the same history. Hashes are computed
+//! once, at creation, by the `*_hashed` constructors of [`RelRc`], using a
+//! selectable [`HashAlgorithm`].
+//!
+//! Values and edge values are fed to the hash function through their [`Hash`]
+//! implementations. Hashes are thus only as stable as these implementations:
+//! integers are written independently of the platform, but the standard
+//! library does not guarantee the output of its implementations across Rust
+//! versions.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use derive_more::{From, Into};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::edge::InnerEdgeData;
+use crate::{EdgeSource, RelRc};
+
+/// A content-addressed hash of a [`RelRc`] object.
+///
+/// See [`InnerData::hash_id`](crate::node::InnerData::hash_id).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelRcHash([u8; 32]);
+
+impl RelRcHash {
+    /// The bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for RelRcHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl fmt::Debug for RelRcHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RelRcHash({self})")
+    }
+}
+
+/// Error type for hashed objects created with a parent that was not hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("The parents of hashed objects must be hashed")]
+pub struct UnhashedParent;
+
+/// A hash function to compute [`RelRcHash`]es.
+///
+/// Implementations must only depend on the sequence of bytes they are fed, so
+/// that hashes are stable across runs and platforms.
+pub trait HashAlgorithm: Default + Clone {
+    /// Feed bytes to the hash function.
+    fn update(&mut self, bytes: &[u8]);
+
+    /// Compute the hash of all bytes fed so far.
+    fn finalize(self) -> RelRcHash;
+}
+
+/// The 64-bit FNV-1a hash function.
+///
+/// Fast but not cryptographic: collisions can be constructed deliberately.
+/// The 64-bit hash is stored in the first 8 bytes of the [`RelRcHash`], in
+/// little-endian order, and the remaining bytes are zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl HashAlgorithm for Fnv1a {
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finalize(self) -> RelRcHash {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&self.0.to_le_bytes());
+        RelRcHash(bytes)
+    }
+}
+
+/// The BLAKE3 cryptographic hash function.
+///
+/// Requires the `blake3` feature.
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Default)]
+pub struct Blake3(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl HashAlgorithm for Blake3 {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finalize(self) -> RelRcHash {
+        RelRcHash(self.0.finalize().into())
+    }
+}
+
+/// Feeds [`Hash`] implementations into a [`HashAlgorithm`].
+///
+/// The default [`Hasher`] methods write integers in native endianness and
+/// `usize` in native width. Integers are instead always written in
+/// little-endian order, and `usize` and `isize` as 64-bit integers, so that
+/// hashes do not depend on the platform.
+struct StableHasher<H>(H);
+
+macro_rules! write_le {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(&mut self, i: $ty) {
+                self.0.update(&i.to_le_bytes());
+            }
+        )*
+    };
+}
+
+impl<H: HashAlgorithm> Hasher for StableHasher<H> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    write_le!(
+        write_u16(u16),
+        write_u32(u32),
+        write_u64(u64),
+        write_u128(u128),
+        write_i16(i16),
+        write_i32(i32),
+        write_i64(i64),
+        write_i128(i128),
+    );
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+
+    /// The first 8 bytes of the hash of all bytes written so far.
+    fn finish(&self) -> u64 {
+        let hash = self.0.clone().finalize();
+        let (bytes, _) = hash.as_bytes().split_first_chunk().expect("32 bytes");
+        u64::from_le_bytes(*bytes)
+    }
+}
+
+/// Compute the hash of a node from its value and incoming edges.
+///
+/// The sources of all (non-dangling) incoming edges must have been hashed.
+pub(crate) fn hash_node<H: HashAlgorithm, N: Hash, E: Hash>(
+    value: &N,
+    incoming: &[InnerEdgeData<N, E>],
+) -> RelRcHash {
+    let mut hasher = StableHasher(H::default());
+    value.hash(&mut hasher);
+    hasher.write_usize(incoming.len());
+    for edge in incoming {
+        let source_hash = edge.upgrade_source().map(|source| {
+            source
+                .hash_id()
+                .expect("sources are checked before hashing")
+        });
+        edge.is_weak().hash(&mut hasher);
+        source_hash.hash(&mut hasher);
+        edge.value().hash(&mut hasher);
+    }
+    hasher.0.finalize()
+}
+
+impl<N: Hash, E: Hash> RelRc<N, E> {
+    /// Create a new [`RelRc<N, E>`] with no parents, and compute its hash
+    /// using `H`.
+    pub fn new_hashed<H: HashAlgorithm>(value: N) -> Self {
+        Self::with_sources_hashed::<H>(value, []).expect("no parents")
+    }
+
+    /// Create a new [`RelRc<N, E>`] with the given list of parent objects, and
+    /// compute its hash using `H`.
+    ///
+    /// Returns an error if any of the parents was not hashed. All objects of a
+    /// history should be hashed using the same algorithm.
+    pub fn with_parents_hashed<H: HashAlgorithm>(
+        value: N,
+        parents: impl IntoIterator<Item = (RelRc<N, E>, E)>,
+    ) -> Result<Self, UnhashedParent> {
+        Self::with_sources_hashed::<H>(
+            value,
+            parents
+                .into_iter()
+                .map(|(parent, edge_value)| (parent.into(), edge_value)),
+        )
+    }
+
+    /// Create a new [`RelRc<N, E>`] with the given list of edge sources, and
+    /// compute its hash using `H`.
+    ///
+    /// The hash of a weak edge covers the hash of its source if it is alive,
+    /// and is otherwise the same for all dangling edges. Returns an error if
+    /// any of the live sources was not hashed.
+    pub fn with_sources_hashed<H: HashAlgorithm>(
+        value: N,
+        sources: impl IntoIterator<Item = (EdgeSource<N, E>, E)>,
+    ) -> Result<Self, UnhashedParent> {
+        let sources = sources.into_iter().collect_vec();
+        let is_hashed = |source: &EdgeSource<N, E>| {
+            source
+                .upgrade()
+                .map_or(true, |source| source.hash_id().is_some())
+        };
+        if !sources.iter().all(|(source, _)| is_hashed(source)) {
+            return Err(UnhashedParent);
+        }
+        Ok(Self::with_sources_and_hash(
+            value,
+            sources,
+            |value, incoming| Some(hash_node::<H, _, _>(value, incoming)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::HistoryGraph;
+
+    fn diamond<H: HashAlgorithm>(edge: u32) -> RelRc<String, u32> {
+        let root = RelRc::new_hashed::<H>("root".to_string());
+        let left =
+            RelRc::with_parents_hashed::<H>("left".to_string(), [(root.clone(), 1)]).unwrap();
+        let right = RelRc::with_parents_hashed::<H>("right".to_string(), [(root, 2)]).unwrap();
+        RelRc::with_parents_hashed::<H>("tip".to_string(), [(left, edge), (right, 4)]).unwrap()
+    }
+
+    #[rstest]
+    #[case::fnv(diamond::<Fnv1a>)]
+    #[cfg_attr(feature = "blake3", case::blake3(diamond::<Blake3>))]
+    fn test_merkle_hash(#[case] diamond: fn(u32) -> RelRc<String, u32>) {
+        let tip = diamond(3);
+        assert_eq!(tip.hash_id(), diamond(3).hash_id());
+        assert_ne!(tip.hash_id(), diamond(5).hash_id());
+        assert!(tip.all_ancestors().all(|n| n.hash_id().is_some()));
+
+        let graph = HistoryGraph::from_nodes([tip.clone()]);
+        let tip_id = graph.all_node_ids().next().unwrap();
+        assert_eq!(graph.hash_id(tip_id), tip.hash_id());
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        // The hash is independent of the platform and of the process
+        let root = RelRc::<_, u64>::new_hashed::<Fnv1a>(42_usize);
+        let child = RelRc::with_sources_hashed::<Fnv1a>(
+            1_usize,
+            [
+                (EdgeSource::Strong(root.clone()), 7),
+                (EdgeSource::Weak(Default::default()), 8),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            root.hash_id().unwrap().to_string(),
+            "cf4e2fbd87404abe000000000000000000000000000000000000000000000000"
+        );
+        assert_ne!(child.hash_id(), root.hash_id());
+        assert!(RelRc::<usize, u64>::new(42).hash_id().is_none());
+
+        // Parents that were not hashed are rejected
+        let unhashed = RelRc::new(3_usize);
+        assert_eq!(
+            RelRc::with_parents_hashed::<Fnv1a>(1, [(unhashed, 7)]).unwrap_err(),
+            UnhashedParent
+        );
+
+        // `Hasher::finish` truncates the hash of the bytes written so far
+        let mut hasher = StableHasher(Fnv1a::default());
+        42_usize.hash(&mut hasher);
+        hasher.write_usize(0);
+        assert_eq!(hasher.finish(), 0xbe4a_4087_bd2f_4ecf);
+    }
+}
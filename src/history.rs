@@ -3,13 +3,27 @@
 //! The graphs can be traversed using the provided APIs or using the `petgraph`
 //! traits, by activating the `petgraph` feature of this crate.
 
+mod closure;
+mod reachability;
+mod set_ops;
+
+pub use closure::Boundary;
+pub use reachability::CausalOrder;
+pub use set_ops::RegistryMismatch;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::iter;
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{edge::InnerEdgeData, Edge, NodeId, Registry, RelRc};
+use crate::registry::{KeyStrategy, SlotKeys};
+use crate::{edge::InnerEdgeData, Edge, NodeId, Registry, RelRc, RelRcHash};
+use reachability::ReachabilityIndex;
 
 use derive_more::{From, Into};
 use derive_where::derive_where;
-use slotmap_fork_lmondada::SecondaryMap;
+use fxhash::FxHashSet;
+use itertools::Itertools;
 
 /// A graph of [`RelRc`] nodes and their dependencies.
 ///
@@ -18,34 +32,54 @@ use slotmap_fork_lmondada::SecondaryMap;
 /// between `RelRc` nodes.
 ///
 /// [`RelRc`] objects must be assigned to copyable IDs using a [`Registry`].
+/// Nodes are identified by the keys of the registry, determined by its
+/// [`KeyStrategy`] `S`.
 #[derive(Debug)]
-#[derive_where(Clone, Default)]
-pub struct HistoryGraph<N, E> {
+#[derive_where(Clone)]
+pub struct HistoryGraph<N, E, S: KeyStrategy = SlotKeys> {
     /// The nodes of the graph
-    nodes: SecondaryMap<NodeId, RelRc<N, E>>,
+    nodes: BTreeMap<S::Key, RelRc<N, E>>,
     /// The map between relrc nodes and node IDs.
-    registry: Rc<RefCell<Registry<N, E>>>,
+    registry: Rc<RefCell<Registry<N, E, S>>>,
+    /// The ancestors of every node, see [`HistoryGraph::is_ancestor`].
+    reachability: ReachabilityIndex<S::Key>,
 }
 
-impl<N, E> HistoryGraph<N, E> {
+impl<N, E> Default for HistoryGraph<N, E> {
+    fn default() -> Self {
+        Self {
+            nodes: Default::default(),
+            registry: Default::default(),
+            reachability: Default::default(),
+        }
+    }
+}
+
+impl<N: 'static, E: 'static> HistoryGraph<N, E> {
     /// Create a new [`HistoryGraph`] from a set of nodes..
     pub fn from_nodes(nodes: impl IntoIterator<Item = RelRc<N, E>>) -> Self {
         Self::new(nodes, Registry::new())
     }
+}
 
+impl<N: 'static, E: 'static, S: KeyStrategy> HistoryGraph<N, E, S> {
     /// Create a new [`HistoryGraph`] with a [`Registry`].
-    pub fn with_registry(registry: impl Into<Rc<RefCell<Registry<N, E>>>>) -> Self {
+    pub fn with_registry(registry: impl Into<Rc<RefCell<Registry<N, E, S>>>>) -> Self {
         Self::new([], registry)
     }
 
     /// Create a new [`HistoryGraph`] from a set of nodes and a registry.
+    ///
+    /// Panics if the key strategy of the registry cannot assign a key to one
+    /// of the nodes.
     pub fn new(
         nodes: impl IntoIterator<Item = RelRc<N, E>>,
-        registry: impl Into<Rc<RefCell<Registry<N, E>>>>,
+        registry: impl Into<Rc<RefCell<Registry<N, E, S>>>>,
     ) -> Self {
         let mut ret = Self {
             nodes: Default::default(),
             registry: registry.into(),
+            reachability: Default::default(),
         };
 
         for node in nodes {
@@ -55,8 +89,110 @@ impl<N, E> HistoryGraph<N, E> {
         ret
     }
 
+    /// Add a `RelRc` node to the `HistoryGraph`.
+    ///
+    /// Returns the `NodeId` of the added node. The node may also be
+    /// registered in other registries, e.g. of other `HistoryGraph`s.
+    ///
+    /// Panics if the key strategy of the registry cannot assign a key to the
+    /// node, see [`HistoryGraph::try_insert_node`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The `RelRc` node to be added to the graph.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeId` - The identifier of the added node.
+    pub fn insert_node(&mut self, node: RelRc<N, E>) -> S::Key {
+        self.try_insert_node(node)
+            .expect("the key strategy could not assign a key to the node")
+    }
+
+    /// Add a `RelRc` node to the `HistoryGraph`.
+    ///
+    /// Returns `None` if the key strategy of the registry cannot assign a key
+    /// to the node. See [`HistoryGraph::insert_node`].
+    pub fn try_insert_node(&mut self, node: RelRc<N, E>) -> Option<S::Key> {
+        if let Some(id) = self.get_node_id(&node) {
+            return Some(id); // Node already exists, return its ID
+        }
+        let id = node.try_register_in(&self.registry)?;
+
+        self.nodes.insert(id, node);
+        self.index_node(id);
+        Some(id)
+    }
+
+    /// Add a `RelRc` node to the `HistoryGraph` with the given key.
+    ///
+    /// Returns `None` if the node could not be registered with this key, see
+    /// [`RelRc::try_register_with_key`].
+    pub fn try_insert_node_with_key(&mut self, node: RelRc<N, E>, key: S::Key) -> Option<S::Key> {
+        let id = node.try_register_with_key(&self.registry, key)?;
+        self.nodes.insert(id, node);
+        self.index_node(id);
+        Some(id)
+    }
+
+    /// Insert `node` and all its ancestors.
+    ///
+    /// Ancestors are always inserted before their descendants. The sources of
+    /// weak edges are not inserted.
+    ///
+    /// Panics if the key strategy of the registry cannot assign a key to one
+    /// of the nodes, see [`HistoryGraph::try_insert_ancestors`].
+    pub fn insert_ancestors(&mut self, node: RelRc<N, E>) -> S::Key {
+        self.try_insert_ancestors(node)
+            .expect("the key strategy could not assign a key to the node")
+    }
+
+    /// Insert `node` and all its ancestors.
+    ///
+    /// Returns `None` if the key strategy of the registry cannot assign a key
+    /// to one of the nodes, in which case the ancestors inserted so far are
+    /// kept. See [`HistoryGraph::insert_ancestors`].
+    pub fn try_insert_ancestors(&mut self, node: RelRc<N, E>) -> Option<S::Key> {
+        let mut stack = vec![node];
+        let mut node_id = None;
+        while let Some(node) = stack.last() {
+            let missing_parents = node
+                .all_parents()
+                .filter(|&p| !self.contains(p))
+                .cloned()
+                .collect_vec();
+            if missing_parents.is_empty() {
+                let node = stack.pop().expect("non-empty stack");
+                node_id = Some(self.try_insert_node(node)?);
+            } else {
+                stack.extend(missing_parents);
+            }
+        }
+        // The last node to be inserted is `node`
+        node_id
+    }
+
+    /// The same graph, with its nodes identified by their IDs in `registry`.
+    ///
+    /// Use this to translate a graph after moving its nodes into another
+    /// registry with [`Registry::move_nodes`] or [`Registry::move_all`]: the
+    /// IDs of the moved nodes no longer resolve in the registry of `self`.
+    /// Nodes already in `registry` keep their ID there, the other nodes are
+    /// registered in it.
+    ///
+    /// Panics if the key strategy of `registry` cannot assign a key to one of
+    /// the nodes that are not in `registry` yet.
+    pub fn remap<T: KeyStrategy>(
+        &self,
+        registry: impl Into<Rc<RefCell<Registry<N, E, T>>>>,
+    ) -> HistoryGraph<N, E, T> {
+        HistoryGraph::new(self.nodes.values().cloned(), registry)
+    }
+}
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
     /// Get all outgoing edge IDs from a node.
-    pub fn outgoing_edges(&self, node_id: NodeId) -> impl Iterator<Item = EdgeId> + '_ {
+    pub fn outgoing_edges(&self, node_id: S::Key) -> impl Iterator<Item = EdgeId<S::Key>> + '_ {
         let source = self.get_node(node_id);
         let edges = source.map(|n| n.all_outgoing());
         let map_node_id = |Edge { target, index }| {
@@ -67,7 +203,7 @@ impl<N, E> HistoryGraph<N, E> {
     }
 
     /// Get all incoming edge IDs from a node.
-    pub fn incoming_edges(&self, node_id: NodeId) -> impl Iterator<Item = EdgeId> + '_ {
+    pub fn incoming_edges(&self, node_id: S::Key) -> impl Iterator<Item = EdgeId<S::Key>> + '_ {
         let target = self.get_node(node_id);
         let n_incoming = target.map(|n| n.n_incoming()).unwrap_or_default();
         (0..n_incoming)
@@ -79,30 +215,163 @@ impl<N, E> HistoryGraph<N, E> {
     }
 
     /// Get all parent node IDs of a node.
-    pub fn parents(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    pub fn parents(&self, node_id: S::Key) -> impl Iterator<Item = S::Key> + '_ {
         self.incoming_edges(node_id)
             .filter_map(|edge_id| self.source(edge_id))
     }
 
     /// Get all child node IDs of a node.
-    pub fn children(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    pub fn children(&self, node_id: S::Key) -> impl Iterator<Item = S::Key> + '_ {
         self.outgoing_edges(node_id)
             .filter_map(|edge_id| self.target(edge_id))
     }
 
+    /// Get the generation of a node, see
+    /// [`InnerData::generation`](crate::node::InnerData::generation).
+    pub fn generation(&self, node_id: S::Key) -> Option<usize> {
+        self.get_node(node_id).map(|n| n.generation())
+    }
+
+    /// Get the content-addressed hash of a node, see
+    /// [`InnerData::hash_id`](crate::node::InnerData::hash_id).
+    pub fn hash_id(&self, node_id: S::Key) -> Option<RelRcHash> {
+        self.get_node(node_id)?.hash_id()
+    }
+
+    /// Iterate over the ancestors of a node in the graph, including the node
+    /// itself.
+    ///
+    /// Ancestors are visited by decreasing generation, so that every node is
+    /// visited before its parents.
+    pub fn ancestors(&self, node_id: S::Key) -> impl Iterator<Item = S::Key> + '_ {
+        self.ancestors_down_to(node_id, 0)
+    }
+
+    /// Iterate over the ancestors of a node in the graph with a generation of
+    /// at least `min_generation`, including the node itself.
+    ///
+    /// As generations strictly decrease along incoming edges, the walk stops
+    /// at nodes of generation `min_generation` and never explores the rest of
+    /// the history. Ancestors are visited by decreasing generation.
+    pub fn ancestors_down_to(
+        &self,
+        node_id: S::Key,
+        min_generation: usize,
+    ) -> impl Iterator<Item = S::Key> + '_ {
+        let mut seen = FxHashSet::from_iter([node_id]);
+        let mut heap = BinaryHeap::from_iter(
+            self.generation(node_id)
+                .filter(|&g| g >= min_generation)
+                .map(|g| (g, node_id)),
+        );
+        iter::from_fn(move || {
+            let (_, node_id) = heap.pop()?;
+            for parent in self.parents(node_id) {
+                let generation = self.generation(parent).expect("parent in graph");
+                if generation >= min_generation && seen.insert(parent) {
+                    heap.push((generation, parent));
+                }
+            }
+            Some(node_id)
+        })
+    }
+
+    /// Iterate over the descendants of a node in the graph, including the node
+    /// itself.
+    ///
+    /// Descendants are visited by increasing generation, so that every node is
+    /// visited before its children.
+    pub fn descendants(&self, node_id: S::Key) -> impl Iterator<Item = S::Key> + '_ {
+        self.descendants_up_to(node_id, usize::MAX)
+    }
+
+    /// Iterate over the descendants of a node in the graph with a generation of
+    /// at most `max_generation`, including the node itself.
+    ///
+    /// As generations strictly increase along outgoing edges, the walk stops
+    /// at nodes of generation `max_generation`. Descendants are visited by
+    /// increasing generation.
+    pub fn descendants_up_to(
+        &self,
+        node_id: S::Key,
+        max_generation: usize,
+    ) -> impl Iterator<Item = S::Key> + '_ {
+        let mut seen = FxHashSet::from_iter([node_id]);
+        let mut heap = BinaryHeap::from_iter(
+            self.generation(node_id)
+                .filter(|&g| g <= max_generation)
+                .map(|g| Reverse((g, node_id))),
+        );
+        iter::from_fn(move || {
+            let Reverse((_, node_id)) = heap.pop()?;
+            for child in self.children(node_id) {
+                let generation = self.generation(child).expect("child in graph");
+                if generation <= max_generation && seen.insert(child) {
+                    heap.push(Reverse((generation, child)));
+                }
+            }
+            Some(node_id)
+        })
+    }
+
+    /// Remove a node from the `HistoryGraph`.
+    ///
+    /// Returns the handle of the graph to the node, or `None` if the node is
+    /// not in the graph. Dropping the handle frees the node and its ancestors
+    /// that are not kept alive otherwise. The edges between the node and the
+    /// rest of the graph are removed along with it; the node remains
+    /// registered until it is dropped.
+    pub fn remove_node(&mut self, node_id: S::Key) -> Option<RelRc<N, E>> {
+        let node = self.nodes.remove(&node_id)?;
+        self.reachability.remove(node_id);
+        Some(node)
+    }
+
+    /// Only keep the nodes for which `keep` returns `true`.
+    ///
+    /// The handles of the graph to the removed nodes are dropped, see
+    /// [`HistoryGraph::remove_node`].
+    pub fn retain(&mut self, mut keep: impl FnMut(S::Key, &RelRc<N, E>) -> bool) {
+        let removed = self
+            .nodes
+            .iter()
+            .filter(|&(&id, node)| !keep(id, node))
+            .map(|(&id, _)| id)
+            .collect_vec();
+        // Drop the handles once the graph is consistent again
+        let removed = removed
+            .into_iter()
+            .filter_map(|id| self.remove_node(id))
+            .collect_vec();
+        drop(removed);
+    }
+
+    /// Only keep the ancestors of `heads` in the graph, including the heads
+    /// themselves.
+    ///
+    /// Heads that are not in the graph are ignored. The handles of the graph
+    /// to the removed nodes are dropped, see [`HistoryGraph::remove_node`].
+    pub fn prune_to(&mut self, heads: impl IntoIterator<Item = S::Key>) {
+        let ancestors: FxHashSet<_> = heads
+            .into_iter()
+            .flat_map(|head| self.ancestors(head))
+            .collect();
+        self.retain(|id, _| ancestors.contains(&id));
+    }
+
     /// Get the registry of the history graph.
-    pub fn registry(&self) -> &Rc<RefCell<Registry<N, E>>> {
+    pub fn registry(&self) -> &Rc<RefCell<Registry<N, E, S>>> {
         &self.registry
     }
 
-    fn get_node_id(&self, node: &RelRc<N, E>) -> Option<NodeId> {
+    fn get_node_id(&self, node: &RelRc<N, E>) -> Option<S::Key> {
         let id = self.registry.borrow().get_id(node)?;
-        self.nodes.contains_key(id).then_some(id)
+        self.nodes.contains_key(&id).then_some(id)
     }
 
     /// Get all nodes in the ancestor graph.
-    pub fn all_node_ids(&self) -> impl Iterator<Item = NodeId> + Clone + '_ {
-        self.nodes.keys()
+    pub fn all_node_ids(&self) -> impl Iterator<Item = S::Key> + Clone + '_ {
+        self.nodes.keys().copied()
     }
 
     /// Check if a node is in the history graph.
@@ -114,76 +383,65 @@ impl<N, E> HistoryGraph<N, E> {
     }
 
     /// Check if a node id is in the history graph.
-    pub fn contains_id(&self, node_id: NodeId) -> bool {
-        self.nodes.contains_key(node_id)
+    pub fn contains_id(&self, node_id: S::Key) -> bool {
+        self.nodes.contains_key(&node_id)
     }
 
     /// Get the node data for a node identifier.
     ///
     /// Panic if the node id is invalid.
-    pub fn get_node(&self, node_id: NodeId) -> Option<&RelRc<N, E>> {
-        self.nodes.get(node_id)
+    pub fn get_node(&self, node_id: S::Key) -> Option<&RelRc<N, E>> {
+        self.nodes.get(&node_id)
     }
 
     /// Get the edge data for an edge identifier.
     ///
     /// Panic if the edge id is invalid.
-    pub fn get_edge(&self, edge_id: EdgeId) -> Option<&InnerEdgeData<N, E>> {
+    pub fn get_edge(&self, edge_id: EdgeId<S::Key>) -> Option<&InnerEdgeData<N, E>> {
         self.get_node(edge_id.target)?
             .incoming(edge_id.index)
-            .filter(|e| self.contains(e.source()))
+            .filter(|e| e.upgrade_source().is_some_and(|s| self.contains(&s)))
     }
 
     /// Check if an edge is in the history graph.
-    pub fn contains_edge(&self, edge_id: EdgeId) -> bool {
+    pub fn contains_edge(&self, edge_id: EdgeId<S::Key>) -> bool {
         self.get_edge(edge_id).is_some()
     }
 
-    /// Get the source node id of an edge.
-    pub fn source(&self, edge_id: EdgeId) -> Option<NodeId> {
-        let edge = self.get_edge(edge_id)?;
-        let source_node = edge.source();
-        self.get_node_id(source_node)
-    }
-
-    /// Get the target node id of an edge.
-    pub fn target(&self, edge_id: EdgeId) -> Option<NodeId> {
-        self.contains_edge(edge_id).then_some(edge_id.target)
+    /// Check if an edge of a node in the history graph is dangling, i.e. it
+    /// is a weak edge whose source has been dropped.
+    ///
+    /// Dangling edges are not contained in the graph.
+    pub fn is_dangling(&self, edge_id: EdgeId<S::Key>) -> bool {
+        self.get_node(edge_id.target)
+            .and_then(|n| n.incoming(edge_id.index))
+            .is_some_and(|e| e.is_dangling())
     }
 
-    /// Add a `RelRc` node to the `HistoryGraph`.
+    /// Get all dangling incoming edge IDs of a node.
     ///
-    /// Returns the `NodeId` of the added node. This may fail if the node is
-    /// already registered in a different registry.
-    ///
-    /// # Arguments
-    ///
-    /// * `node` - The `RelRc` node to be added to the graph.
-    ///
-    /// # Returns
-    ///
-    /// * `NodeId` - The identifier of the added node.
-    pub fn insert_node(&mut self, node: RelRc<N, E>) -> Option<NodeId> {
-        if let Some(id) = self.get_node_id(&node) {
-            return Some(id); // Node already exists, return its ID
-        }
-        let id = node.try_register_in(&self.registry)?;
+    /// See [`HistoryGraph::is_dangling`].
+    pub fn dangling_edges(&self, node_id: S::Key) -> impl Iterator<Item = EdgeId<S::Key>> + '_ {
+        let target = self.get_node(node_id);
+        let n_incoming = target.map(|n| n.n_incoming()).unwrap_or_default();
+        (0..n_incoming)
+            .map(move |index| EdgeId {
+                target: node_id,
+                index,
+            })
+            .filter(|&e| self.is_dangling(e))
+    }
 
-        self.nodes.insert(id, node);
-        Some(id)
+    /// Get the source node id of an edge.
+    pub fn source(&self, edge_id: EdgeId<S::Key>) -> Option<S::Key> {
+        let edge = self.get_edge(edge_id)?;
+        let source_node = edge.upgrade_source()?;
+        self.get_node_id(&source_node)
     }
 
-    /// Insert `node` and all its ancestors.
-    ///
-    /// This will panic if `node` or any of its ancestors are already registered
-    /// in a different registry.
-    pub fn insert_ancestors(&mut self, node: RelRc<N, E>) -> NodeId {
-        for parent in node.all_parents() {
-            if !self.contains(parent) {
-                self.insert_ancestors(parent.clone());
-            }
-        }
-        self.insert_node(node.clone()).expect("node not registered")
+    /// Get the target node id of an edge.
+    pub fn target(&self, edge_id: EdgeId<S::Key>) -> Option<S::Key> {
+        self.contains_edge(edge_id).then_some(edge_id.target)
     }
 }
 
@@ -191,19 +449,18 @@ impl<N, E> HistoryGraph<N, E> {
 ///
 /// The edge is uniquely identified by the edge target and the index of the
 /// incoming edge at the target.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, From, Into)]
-pub struct EdgeId {
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, From, Into)]
+pub struct EdgeId<K = NodeId> {
     /// The edge target.
-    pub target: NodeId,
+    pub target: K,
     /// The incoming index of the edge at the target.
     pub index: usize,
 }
 
 #[cfg(test)]
 mod tests {
-    #![cfg(feature = "serde")]
+    #[cfg(feature = "serde")]
     use insta::assert_yaml_snapshot;
-    use itertools::Itertools;
 
     use super::*;
     use crate::RelRc;
@@ -265,6 +522,92 @@ mod tests {
         assert_eq!(node_ids, [grandparent_id, parent_id, child1_id, child2_id]);
     }
 
+    #[test]
+    fn test_remap() {
+        let from = Rc::new(RefCell::new(Registry::<&str, ()>::new()));
+        let to = Rc::new(RefCell::new(Registry::new()));
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_parents("child", [(parent.clone(), ())]);
+        let graph = HistoryGraph::new([parent.clone(), child.clone()], from.clone());
+
+        let id_map = Registry::move_all(&from, &to);
+        let graph = graph.remap(to.clone());
+        let [parent_id, child_id] = [&parent, &child].map(|n| graph.get_node_id(n).unwrap());
+        assert_eq!(
+            id_map.values().copied().sorted().collect_vec(),
+            [parent_id, child_id]
+        );
+        assert!(graph.contains(&parent) && graph.contains(&child));
+        assert_eq!(graph.parents(child_id).collect_vec(), [parent_id]);
+        assert!(graph.is_ancestor(parent_id, child_id));
+        assert!(Rc::ptr_eq(graph.registry(), &to));
+    }
+
+    #[test]
+    fn test_remove_nodes() {
+        let a = RelRc::new("a");
+        let b = RelRc::with_parents("b", [(a.clone(), ())]);
+        let c = RelRc::with_parents("c", [(a.clone(), ())]);
+        let d = RelRc::with_parents("d", [(b.clone(), ())]);
+        let c_weak = c.downgrade();
+
+        let mut graph = HistoryGraph::from_nodes([&a, &b, &c, &d].map(RelRc::clone));
+        let id = |n: &RelRc<_, _>| graph.registry().borrow().get_id(n).unwrap();
+        let (a_id, b_id, c_id, d_id) = (id(&a), id(&b), id(&c), id(&d));
+        drop((a, b, c, d));
+
+        let removed = graph.remove_node(b_id).unwrap();
+        assert_eq!(removed.value(), &"b");
+        assert!(graph.remove_node(b_id).is_none());
+        assert_eq!(graph.parents(d_id).count(), 0);
+        assert!(!graph.is_ancestor(a_id, d_id));
+        drop(removed);
+
+        // Pruning drops the handles of the graph to the removed nodes
+        graph.prune_to([d_id]);
+        assert_eq!(graph.all_node_ids().collect_vec(), [d_id]);
+        assert!(c_weak.upgrade().is_none());
+        assert!(!graph.registry().borrow().contains_id(c_id));
+        assert_eq!(graph.get_node(d_id).unwrap().all_ancestors().count(), 3);
+
+        graph.retain(|_, node| node.value() != &"d");
+        assert!(graph.all_node_ids().next().is_none());
+        assert!(graph.registry().borrow().is_empty());
+    }
+
+    #[test]
+    fn test_generation_walks() {
+        // a -> b -> d, a -> c -> d, and an unrelated root e
+        let a = RelRc::new("a");
+        let b = RelRc::with_parents("b", [(a.clone(), ())]);
+        let c = RelRc::with_parents("c", [(a.clone(), ())]);
+        let d = RelRc::with_parents("d", [(b.clone(), ()), (c.clone(), ())]);
+        let e = RelRc::new("e");
+
+        let graph = HistoryGraph::from_nodes([&a, &b, &c, &d, &e].map(RelRc::clone));
+        let id = |n: &RelRc<_, _>| graph.registry().borrow().get_id(n).unwrap();
+        let values = |ids: Vec<NodeId>| {
+            ids.into_iter()
+                .map(|n| *graph.get_node(n).unwrap().value())
+                .collect_vec()
+        };
+        assert_eq!(graph.generation(id(&a)), Some(0));
+        assert_eq!(graph.generation(id(&d)), Some(2));
+
+        let ancestors = values(graph.ancestors(id(&d)).collect());
+        assert_eq!(ancestors.first(), Some(&"d"));
+        assert_eq!(ancestors.last(), Some(&"a"));
+        assert_eq!(ancestors.len(), 4);
+        let recent = values(graph.ancestors_down_to(id(&d), 1).collect());
+        assert_eq!(recent.iter().sorted().collect_vec(), [&"b", &"c", &"d"]);
+
+        let descendants = values(graph.descendants(id(&a)).collect());
+        assert_eq!(descendants.first(), Some(&"a"));
+        assert_eq!(descendants.last(), Some(&"d"));
+        assert_eq!(graph.descendants_up_to(id(&a), 1).count(), 3);
+        assert_eq!(graph.descendants(id(&e)).count(), 1);
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn test_history_graph_serialization() {
@@ -287,7 +630,7 @@ mod tests {
         graph.insert_ancestors(child1);
         graph.insert_ancestors(child2);
 
-        let serialized = graph.to_serialized();
+        let serialized = graph.to_serialized().unwrap();
         assert_yaml_snapshot!(serialized);
 
         let deserialized = HistoryGraph::from_serialized(serialized);
@@ -0,0 +1,136 @@
+//! Ancestor and descendant closures of [`HistoryGraph`]s.
+//!
+//! A graph may contain any subset of nodes: the edges between its nodes and
+//! nodes outside of the graph are not part of the graph. The closures add the
+//! missing nodes, and [`HistoryGraph::boundary`] lists the edges that cross
+//! the boundary of the graph.
+
+use derive_where::derive_where;
+use itertools::Itertools;
+
+use super::{EdgeId, HistoryGraph};
+use crate::registry::{KeyStrategy, NodeId};
+use crate::Edge;
+
+/// The edges between the nodes of a [`HistoryGraph`] and nodes outside of the
+/// graph, see [`HistoryGraph::boundary`].
+#[derive(Debug)]
+#[derive_where(Clone; K)]
+#[derive_where(Default)]
+pub struct Boundary<N, E, K = NodeId> {
+    /// The incoming edges of nodes of the graph whose source is alive but not
+    /// in the graph.
+    pub incoming: Vec<EdgeId<K>>,
+    /// The outgoing edges of nodes of the graph whose target is not in the
+    /// graph.
+    pub outgoing: Vec<Edge<N, E>>,
+}
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// Whether the graph contains the ancestors of all of its nodes.
+    ///
+    /// As for [`crate::RelRc::all_ancestors`], only strong edges are
+    /// followed: the sources of weak edges are not ancestors.
+    pub fn is_ancestor_closed(&self) -> bool {
+        self.nodes
+            .values()
+            .all(|node| node.all_parents().all(|parent| self.contains(parent)))
+    }
+
+    /// The edges that cross the boundary of the graph.
+    ///
+    /// These are the edges that are not in the graph, as one of their ends is
+    /// not in the graph. Dangling edges, whose source has been dropped, are
+    /// not included: see [`HistoryGraph::dangling_edges`].
+    pub fn boundary(&self) -> Boundary<N, E, S::Key> {
+        let mut boundary = Boundary::default();
+        for (&target, node) in &self.nodes {
+            for (index, edge) in node.all_incoming().iter().enumerate() {
+                if edge.upgrade_source().is_some_and(|s| !self.contains(&s)) {
+                    boundary.incoming.push(EdgeId { target, index });
+                }
+            }
+            boundary.outgoing.extend(
+                node.all_outgoing()
+                    .into_iter()
+                    .filter(|edge| !self.contains(edge.target())),
+            );
+        }
+        boundary
+    }
+}
+
+impl<N: 'static, E: 'static, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// Insert the ancestors of all nodes of the graph.
+    ///
+    /// The graph is then ancestor-closed, see
+    /// [`HistoryGraph::is_ancestor_closed`]. Panics if the key strategy of the
+    /// registry cannot assign a key to one of the nodes.
+    pub fn close_under_ancestors(&mut self) {
+        let nodes = self.nodes.values().cloned().collect_vec();
+        for node in nodes {
+            self.insert_ancestors(node);
+        }
+    }
+
+    /// Insert the live descendants of all nodes of the graph.
+    ///
+    /// All outgoing edges are followed, including weak edges: the children of
+    /// a node are all nodes with an incoming edge from it. Panics if the key
+    /// strategy of the registry cannot assign a key to one of the nodes.
+    pub fn close_under_descendants(&mut self) {
+        let descendants = self
+            .nodes
+            .values()
+            .flat_map(|node| node.all_descendants())
+            .filter(|node| !self.contains(node))
+            .unique_by(|node| node.as_ptr())
+            .sorted_by_key(|node| node.generation())
+            .collect_vec();
+        for node in descendants {
+            self.insert_node(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EdgeSource, HistoryGraph, RelRc};
+
+    use super::*;
+
+    #[test]
+    fn test_closures() {
+        let root = RelRc::new("root");
+        let a = RelRc::with_parents("a", [(root.clone(), 1)]);
+        let b = RelRc::with_parents("b", [(a.clone(), 2)]);
+        let weak_child = RelRc::with_sources("weak", [(EdgeSource::Weak(b.downgrade()), 3)]);
+
+        let mut graph = HistoryGraph::from_nodes([a.clone()]);
+        let a_id = graph.all_node_ids().exactly_one().ok().unwrap();
+        assert!(!graph.is_ancestor_closed());
+        let boundary = graph.boundary();
+        assert_eq!(
+            boundary.incoming,
+            [EdgeId {
+                target: a_id,
+                index: 0
+            }]
+        );
+        assert_eq!(
+            boundary.outgoing.iter().map(|e| *e.value()).collect_vec(),
+            [2]
+        );
+
+        graph.close_under_descendants();
+        assert_eq!(graph.all_node_ids().count(), 3);
+        assert!(graph.contains(&weak_child));
+        assert_eq!(graph.boundary().outgoing.len(), 0);
+
+        graph.close_under_ancestors();
+        assert!(graph.is_ancestor_closed());
+        assert!(graph.contains(&root));
+        let boundary = graph.boundary();
+        assert!(boundary.incoming.is_empty() && boundary.outgoing.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+//! Reachability queries between the nodes of a [`HistoryGraph`].
+//!
+//! Every node of the graph is assigned a small Bloom filter over the keys of
+//! its ancestors in the graph, maintained incrementally as nodes are inserted.
+//! Together with generation numbers, the filters rule out most non-ancestors
+//! without walking the graph; the remaining candidates are confirmed by a
+//! walk that only visits nodes whose filters may contain the ancestor.
+
+use std::hash::Hash;
+
+use fxhash::{FxHashMap, FxHashSet};
+use itertools::Itertools;
+
+use super::HistoryGraph;
+use crate::registry::KeyStrategy;
+use crate::RelRc;
+
+/// The causal order between two nodes of a [`HistoryGraph`], see
+/// [`HistoryGraph::compare`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CausalOrder {
+    /// The first node is a strict ancestor of the second.
+    Ancestor,
+    /// The first node is a strict descendant of the second.
+    Descendant,
+    /// The nodes are the same.
+    Equal,
+    /// Neither node is an ancestor of the other.
+    Concurrent,
+}
+
+/// A Bloom filter over a set of keys.
+type Filter = u128;
+
+/// The filter containing only `key`.
+fn key_filter<K: Hash>(key: &K) -> Filter {
+    let hash = fxhash::hash64(key);
+    (1 << (hash % 128)) | (1 << ((hash >> 7) % 128))
+}
+
+/// An index of the ancestors of every node of a [`HistoryGraph`].
+///
+/// The filter of a node contains the node and all of its ancestors in the
+/// graph. Filters may also contain keys that are not ancestors, e.g. of nodes
+/// that have since been removed from the graph.
+#[derive(Debug, Clone)]
+pub(super) struct ReachabilityIndex<K> {
+    filters: FxHashMap<K, Filter>,
+}
+
+impl<K> Default for ReachabilityIndex<K> {
+    fn default() -> Self {
+        Self {
+            filters: FxHashMap::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> ReachabilityIndex<K> {
+    /// Remove a node from the index.
+    ///
+    /// The filters of its descendants are left unchanged: they may still
+    /// contain the node and its ancestors.
+    pub(super) fn remove(&mut self, node: K) {
+        self.filters.remove(&node);
+    }
+
+    /// Whether `ancestor` may be an ancestor of `node`.
+    fn may_reach(&self, ancestor: &K, node: &K) -> bool {
+        let filter = key_filter(ancestor);
+        self.filters
+            .get(node)
+            .is_some_and(|&node_filter| node_filter & filter == filter)
+    }
+}
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// Whether the node `ancestor` is an ancestor of the node `node` in the
+    /// graph.
+    ///
+    /// As for [`HistoryGraph::ancestors`], every node is an ancestor of
+    /// itself. Returns `false` if either node is not in the graph.
+    pub fn is_ancestor(&self, ancestor: S::Key, node: S::Key) -> bool {
+        let (Some(min_generation), Some(_)) = (self.generation(ancestor), self.generation(node))
+        else {
+            return false;
+        };
+        if ancestor == node {
+            return true;
+        }
+        // Generations strictly decrease along edges: only nodes of a larger
+        // generation than `ancestor` can be its descendants
+        let may_reach = |id: S::Key| {
+            self.generation(id).expect("node in graph") > min_generation
+                && self.reachability.may_reach(&ancestor, &id)
+        };
+        if !may_reach(node) {
+            return false;
+        }
+        let mut seen = FxHashSet::from_iter([node]);
+        let mut stack = vec![node];
+        while let Some(id) = stack.pop() {
+            for parent in self.parents(id) {
+                if parent == ancestor {
+                    return true;
+                }
+                if seen.insert(parent) && may_reach(parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        false
+    }
+
+    /// The causal order between the nodes `a` and `b` of the graph.
+    ///
+    /// Returns `None` if either node is not in the graph. See
+    /// [`HistoryGraph::is_ancestor`].
+    pub fn compare(&self, a: S::Key, b: S::Key) -> Option<CausalOrder> {
+        if !self.contains_id(a) || !self.contains_id(b) {
+            return None;
+        }
+        let order = if a == b {
+            CausalOrder::Equal
+        } else if self.is_ancestor(a, b) {
+            CausalOrder::Ancestor
+        } else if self.is_ancestor(b, a) {
+            CausalOrder::Descendant
+        } else {
+            CausalOrder::Concurrent
+        };
+        Some(order)
+    }
+
+    /// Add a node of the graph to the reachability index.
+    ///
+    /// The descendants of the node that are already in the graph are updated
+    /// to include the node and its ancestors.
+    pub(super) fn index_node(&mut self, node_id: S::Key) {
+        let node = &self.nodes[&node_id];
+        let (filter, children) = {
+            let registry = self.registry.borrow();
+            let graph_id = |node: &RelRc<N, E>| {
+                let id = registry.get_id(node)?;
+                self.nodes.contains_key(&id).then_some(id)
+            };
+            let filter = node
+                .all_incoming()
+                .iter()
+                .filter_map(|edge| match edge.source() {
+                    Some(parent) => graph_id(parent),
+                    None => graph_id(&edge.upgrade_source()?),
+                })
+                .filter_map(|parent| self.reachability.filters.get(&parent))
+                .fold(key_filter(&node_id), |filter, &parent| filter | parent);
+            let children = node
+                .all_children()
+                .filter_map(|child| graph_id(&child))
+                .collect_vec();
+            (filter, children)
+        };
+        self.reachability.filters.insert(node_id, filter);
+
+        // Propagate the filter to the descendants already in the graph
+        let mut stack = vec![(children, filter)];
+        while let Some((children, filter)) = stack.pop() {
+            for child in children {
+                let child_filter = self.reachability.filters.entry(child).or_default();
+                if *child_filter | filter != *child_filter {
+                    *child_filter |= filter;
+                    let child_filter = *child_filter;
+                    stack.push((self.children(child).collect_vec(), child_filter));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HistoryGraph;
+
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let root = RelRc::<_, ()>::new("root");
+        let left = RelRc::with_parents("left", [(root.clone(), ())]);
+        let right = RelRc::with_parents("right", [(root.clone(), ())]);
+        let tip = RelRc::with_parents("tip", [(left.clone(), ())]);
+
+        // Descendants are inserted before their ancestors
+        let mut graph = HistoryGraph::default();
+        let tip_id = graph.insert_node(tip);
+        let right_id = graph.insert_node(right);
+        let root_id = graph.insert_node(root);
+        assert_eq!(
+            graph.compare(root_id, tip_id),
+            Some(CausalOrder::Concurrent)
+        );
+        let left_id = graph.insert_node(left);
+
+        assert!(graph.is_ancestor(root_id, tip_id));
+        assert!(graph.is_ancestor(tip_id, tip_id));
+        assert!(!graph.is_ancestor(tip_id, root_id));
+        assert!(!graph.is_ancestor(right_id, tip_id));
+        assert_eq!(graph.compare(root_id, tip_id), Some(CausalOrder::Ancestor));
+        assert_eq!(
+            graph.compare(tip_id, left_id),
+            Some(CausalOrder::Descendant)
+        );
+        assert_eq!(graph.compare(left_id, left_id), Some(CausalOrder::Equal));
+        assert_eq!(
+            graph.compare(right_id, tip_id),
+            Some(CausalOrder::Concurrent)
+        );
+
+        let other = RelRc::new("other");
+        let other_id = other.register_in(graph.registry());
+        assert_eq!(graph.compare(root_id, other_id), None);
+    }
+}
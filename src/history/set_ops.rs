@@ -0,0 +1,133 @@
+//! Set operations between [`HistoryGraph`]s that share a registry.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::HistoryGraph;
+use crate::registry::KeyStrategy;
+use crate::RelRc;
+
+/// Error type for set operations between graphs with different registries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("History graphs do not share the same registry")]
+pub struct RegistryMismatch;
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// The graph with the nodes of both `self` and `other`.
+    ///
+    /// Returns an error if the graphs do not share the same registry.
+    pub fn union(&self, other: &Self) -> Result<Self, RegistryMismatch> {
+        self.check_registry(other)?;
+        let mut nodes = self.nodes.clone();
+        nodes.extend(other.nodes.iter().map(|(&id, node)| (id, node.clone())));
+        Ok(self.with_nodes(nodes))
+    }
+
+    /// The graph with the nodes of `self` that are also in `other`.
+    ///
+    /// Returns an error if the graphs do not share the same registry.
+    pub fn intersection(&self, other: &Self) -> Result<Self, RegistryMismatch> {
+        self.check_registry(other)?;
+        Ok(self.filter_nodes(|id| other.contains_id(id)))
+    }
+
+    /// The graph with the nodes of `self` that are not in `other`.
+    ///
+    /// Returns an error if the graphs do not share the same registry.
+    pub fn difference(&self, other: &Self) -> Result<Self, RegistryMismatch> {
+        self.check_registry(other)?;
+        Ok(self.filter_nodes(|id| !other.contains_id(id)))
+    }
+
+    /// Whether all nodes of `self` are in `other`.
+    ///
+    /// The edges of a graph are the edges between its nodes, so `self` is
+    /// then a subgraph of `other`. Returns an error if the graphs do not share
+    /// the same registry.
+    pub fn is_subgraph(&self, other: &Self) -> Result<bool, RegistryMismatch> {
+        self.check_registry(other)?;
+        Ok(self.all_node_ids().all(|id| other.contains_id(id)))
+    }
+
+    fn check_registry(&self, other: &Self) -> Result<(), RegistryMismatch> {
+        if Rc::ptr_eq(&self.registry, &other.registry) {
+            Ok(())
+        } else {
+            Err(RegistryMismatch)
+        }
+    }
+
+    /// The graph with the nodes of `self` whose IDs satisfy `keep`.
+    fn filter_nodes(&self, keep: impl Fn(S::Key) -> bool) -> Self {
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|&(&id, _)| keep(id))
+            .map(|(&id, node)| (id, node.clone()))
+            .collect();
+        self.with_nodes(nodes)
+    }
+
+    /// A graph with the given nodes of the registry of `self`.
+    fn with_nodes(&self, nodes: BTreeMap<S::Key, RelRc<N, E>>) -> Self {
+        let mut graph = Self {
+            nodes,
+            registry: self.registry.clone(),
+            reachability: Default::default(),
+        };
+        // Index ancestors first, so that no updates are propagated
+        let ids = graph
+            .nodes
+            .iter()
+            .sorted_by_key(|(_, node)| node.generation())
+            .map(|(&id, _)| id)
+            .collect_vec();
+        for id in ids {
+            graph.index_node(id);
+        }
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CausalOrder, HistoryGraph, RelRc};
+
+    use super::*;
+
+    #[test]
+    fn test_set_operations() {
+        let root = RelRc::<_, ()>::new("root");
+        let a = RelRc::with_parents("a", [(root.clone(), ())]);
+        let b = RelRc::with_parents("b", [(root.clone(), ())]);
+
+        let mut branch_a = HistoryGraph::default();
+        branch_a.insert_ancestors(a);
+        let mut branch_b = HistoryGraph::with_registry(branch_a.registry().clone());
+        let b_id = branch_b.insert_ancestors(b);
+        let id = |n: &RelRc<_, _>| branch_a.registry().borrow().get_id(n).unwrap();
+        let ids = |graph: HistoryGraph<_, _>| graph.all_node_ids().collect_vec();
+
+        let union = branch_a.union(&branch_b).unwrap();
+        assert_eq!(union.all_node_ids().count(), 3);
+        assert_eq!(union.compare(id(&root), b_id), Some(CausalOrder::Ancestor));
+        assert_eq!(ids(branch_a.intersection(&branch_b).unwrap()), [id(&root)]);
+        let only_a = branch_a.difference(&branch_b).unwrap();
+        assert_eq!(only_a.all_node_ids().count(), 1);
+        assert_eq!(
+            only_a
+                .parents(only_a.all_node_ids().next().unwrap())
+                .count(),
+            0
+        );
+
+        assert_eq!(only_a.is_subgraph(&branch_a), Ok(true));
+        assert_eq!(branch_a.is_subgraph(&branch_b), Ok(false));
+        let other = HistoryGraph::from_nodes([root]);
+        assert_eq!(branch_a.union(&other).unwrap_err(), RegistryMismatch);
+        assert_eq!(other.is_subgraph(&branch_a), Err(RegistryMismatch));
+    }
+}
@@ -0,0 +1,243 @@
+//! Compare, hash and order [`RelRc`] objects by identity.
+//!
+//! [`RelRc`] does not implement [`Hash`], [`Eq`] or [`Ord`], as there is no
+//! canonical choice between comparing objects by value or by identity. Wrap
+//! references in [`ByPtr`] or [`ByCreation`] to use them as keys of hash maps
+//! and ordered maps, keyed by identity.
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::node::InnerData;
+use crate::{RelRc, RelWeak};
+
+/// A reference to a [`RelRc`] object, strong or weak.
+pub trait NodePtr {
+    /// The address of the object.
+    ///
+    /// The address is unique among all objects that are alive or that are
+    /// still weakly referenced.
+    fn node_addr(&self) -> *const ();
+
+    /// The creation order of the object, if it is still alive.
+    ///
+    /// See [`InnerData::creation_order`](crate::node::InnerData::creation_order).
+    fn node_creation_order(&self) -> Option<u64>;
+}
+
+impl<N, E> NodePtr for RelRc<N, E> {
+    fn node_addr(&self) -> *const () {
+        self.as_ptr() as *const ()
+    }
+
+    fn node_creation_order(&self) -> Option<u64> {
+        Some(InnerData::creation_order(self))
+    }
+}
+
+impl<N, E> NodePtr for RelWeak<N, E> {
+    fn node_addr(&self) -> *const () {
+        self.as_ptr() as *const ()
+    }
+
+    fn node_creation_order(&self) -> Option<u64> {
+        self.upgrade().map(|node| node.creation_order())
+    }
+}
+
+impl<T: NodePtr> NodePtr for &T {
+    fn node_addr(&self) -> *const () {
+        (*self).node_addr()
+    }
+
+    fn node_creation_order(&self) -> Option<u64> {
+        (*self).node_creation_order()
+    }
+}
+
+/// A reference to a [`RelRc`] object that is hashed, compared and ordered by
+/// the address of the object.
+///
+/// Equality is stable for as long as the wrapped reference exists, even if
+/// it is a [`RelWeak`] to an object that has been dropped. The order is
+/// arbitrary and may differ between runs; use [`ByCreation`] for a
+/// deterministic order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByPtr<T>(pub T);
+
+impl<T> ByPtr<T> {
+    /// Unwrap the reference.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: NodePtr> PartialEq for ByPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.node_addr() == other.0.node_addr()
+    }
+}
+
+impl<T: NodePtr> Eq for ByPtr<T> {}
+
+impl<T: NodePtr> Hash for ByPtr<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.node_addr().hash(state);
+    }
+}
+
+impl<T: NodePtr> PartialOrd for ByPtr<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: NodePtr> Ord for ByPtr<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.node_addr().cmp(&other.0.node_addr())
+    }
+}
+
+impl<T> Deref for ByPtr<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> From<T> for ByPtr<T> {
+    fn from(node: T) -> Self {
+        Self(node)
+    }
+}
+
+/// A reference to a [`RelRc`] object that is hashed, compared and ordered by
+/// the creation order of the object.
+///
+/// Objects created earlier are smaller, so ancestors always come before their
+/// descendants. The creation order is recorded when the reference is wrapped,
+/// so that comparisons remain consistent after the object has been dropped.
+#[derive(Debug, Clone, Copy)]
+pub struct ByCreation<T> {
+    creation_order: u64,
+    node: T,
+}
+
+impl<T: NodePtr> ByCreation<T> {
+    /// Wrap a reference to an object.
+    ///
+    /// Returns `None` if the object is no longer alive.
+    pub fn try_new(node: T) -> Option<Self> {
+        let creation_order = node.node_creation_order()?;
+        Some(Self {
+            creation_order,
+            node,
+        })
+    }
+}
+
+impl<T> ByCreation<T> {
+    /// The creation order of the object.
+    pub fn creation_order(&self) -> u64 {
+        self.creation_order
+    }
+
+    /// Unwrap the reference.
+    pub fn into_inner(self) -> T {
+        self.node
+    }
+}
+
+impl<T> PartialEq for ByCreation<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.creation_order == other.creation_order
+    }
+}
+
+impl<T> Eq for ByCreation<T> {}
+
+impl<T> Hash for ByCreation<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.creation_order.hash(state);
+    }
+}
+
+impl<T> PartialOrd for ByCreation<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ByCreation<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.creation_order.cmp(&other.creation_order)
+    }
+}
+
+impl<T> Deref for ByCreation<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node
+    }
+}
+
+impl<N, E> From<RelRc<N, E>> for ByCreation<RelRc<N, E>> {
+    fn from(node: RelRc<N, E>) -> Self {
+        Self::try_new(node).expect("strong references are alive")
+    }
+}
+
+impl<'a, N, E> From<&'a RelRc<N, E>> for ByCreation<&'a RelRc<N, E>> {
+    fn from(node: &'a RelRc<N, E>) -> Self {
+        Self::try_new(node).expect("strong references are alive")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use itertools::Itertools;
+
+    use super::*;
+
+    // Hashing and ordering only depend on the identity of the objects
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn test_by_ptr() {
+        let a = RelRc::<_, ()>::new("a");
+        let b = RelRc::new("a");
+        let set = HashSet::from([ByPtr(a.clone()), ByPtr(a.clone()), ByPtr(b.clone())]);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&ByPtr(a.clone())));
+
+        // Weak references remain comparable after the object is dropped
+        let weak_b = b.downgrade();
+        let weak_set = HashSet::from([ByPtr(a.downgrade()), ByPtr(weak_b.clone())]);
+        drop(b);
+        assert!(weak_set.contains(&ByPtr(weak_b.clone())));
+        assert_eq!(weak_b, weak_b.clone());
+        assert_ne!(weak_b, a.downgrade());
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn test_by_creation() {
+        let root = RelRc::new("root");
+        let child = RelRc::with_parents("child", [(root.clone(), ())]);
+        let other = RelRc::new("other");
+        assert!(root.creation_order() < child.creation_order());
+
+        let sorted = BTreeSet::from_iter([&other, &child, &root].map(ByCreation::from));
+        let values = sorted.iter().map(|n| *n.value()).collect_vec();
+        assert_eq!(values, ["root", "child", "other"]);
+
+        let weak = ByCreation::try_new(child.downgrade()).unwrap();
+        drop(child);
+        assert!(ByCreation::try_new(weak.clone().into_inner()).is_none());
+        assert_eq!(weak, weak.clone());
+    }
+}
@@ -0,0 +1,279 @@
+//! Hash-consing of [`RelRc`] objects.
+//!
+//! An [`Interner`] returns an existing live object instead of creating a new
+//! one when an object with the same content is requested. The content of an
+//! object is summarised by a key, computed from its value and its parents by a
+//! user-supplied function. Parents are identified by their key in the
+//! registry of the interner, a [`NodeId`] by default.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use itertools::Itertools;
+
+use crate::registry::{KeyStrategy, RegistryEvent, SlotKeys, Subscription};
+use crate::{NodeId, Registry, RelRc};
+
+/// The default content key of an [`Interner`]: the value of the object, and
+/// the IDs of its parents with the values of the edges to them.
+pub type InternKey<N, E, I = NodeId> = (N, Vec<(I, E)>);
+
+/// A function computing the content key of an object from its value and its
+/// parents.
+type KeyFn<N, E, K, I> = dyn Fn(&N, &[(I, &E)]) -> K;
+
+/// The interned objects, by key and by ID.
+struct InternTable<K, I> {
+    by_key: HashMap<K, I>,
+    by_id: HashMap<I, K>,
+}
+
+impl<K: Hash + Eq, I: Copy + Hash + Eq> InternTable<K, I> {
+    /// Remove the entry of a freed ID.
+    fn remove(&mut self, id: I) {
+        let Some(key) = self.by_id.remove(&id) else {
+            return;
+        };
+        if self.by_key.get(&key) == Some(&id) {
+            self.by_key.remove(&key);
+        }
+    }
+}
+
+/// A registry-backed table of live [`RelRc`] objects by content.
+///
+/// Objects created using [`Interner::intern`] or
+/// [`RelRc::with_parents_interned`] are registered in the registry of the
+/// interner. Creating an object with the same key as a live interned object
+/// returns the existing object. Entries are released when their objects are
+/// dropped: the interner does not keep objects alive.
+///
+/// Objects are identified by the keys of the registry, determined by its
+/// [`KeyStrategy`] `S`.
+pub struct Interner<N, E, S: KeyStrategy = SlotKeys, K = InternKey<N, E, <S as KeyStrategy>::Key>> {
+    registry: Rc<RefCell<Registry<N, E, S>>>,
+    table: Rc<RefCell<InternTable<K, S::Key>>>,
+    key_fn: Box<KeyFn<N, E, K, S::Key>>,
+    _subscription: Subscription,
+}
+
+impl<N, E> Interner<N, E>
+where
+    N: Clone + Hash + Eq + 'static,
+    E: Clone + Hash + Eq + 'static,
+{
+    /// Create an interner with a new registry.
+    ///
+    /// Objects are interned by value, parents and edge values.
+    pub fn new() -> Self {
+        Self::with_registry(Registry::new())
+    }
+}
+
+impl<N, E, S: KeyStrategy> Interner<N, E, S>
+where
+    N: Clone + Hash + Eq + 'static,
+    E: Clone + Hash + Eq + 'static,
+{
+    /// Create an interner backed by the given registry.
+    ///
+    /// Objects are interned by value, parents and edge values.
+    pub fn with_registry(registry: impl Into<Rc<RefCell<Registry<N, E, S>>>>) -> Self {
+        Self::with_key(registry, |value, parents| {
+            let parents = parents
+                .iter()
+                .map(|&(id, edge)| (id, edge.clone()))
+                .collect();
+            (value.clone(), parents)
+        })
+    }
+}
+
+impl<N, E> Default for Interner<N, E>
+where
+    N: Clone + Hash + Eq + 'static,
+    E: Clone + Hash + Eq + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: 'static, E: 'static, S: KeyStrategy, K: Clone + Hash + Eq + 'static> Interner<N, E, S, K> {
+    /// Create an interner backed by the given registry, with a custom
+    /// content key.
+    ///
+    /// `key_fn` computes the key of an object from its value and its parents,
+    /// given by their IDs in the registry and the values of the edges to them.
+    /// Objects with equal keys are considered identical.
+    pub fn with_key(
+        registry: impl Into<Rc<RefCell<Registry<N, E, S>>>>,
+        key_fn: impl Fn(&N, &[(S::Key, &E)]) -> K + 'static,
+    ) -> Self {
+        let registry = registry.into();
+        let table = Rc::new(RefCell::new(InternTable {
+            by_key: HashMap::new(),
+            by_id: HashMap::new(),
+        }));
+        let weak_table = Rc::downgrade(&table);
+        let subscription = registry.borrow_mut().subscribe(move |event| {
+            if let (RegistryEvent::Freed(id), Some(table)) = (event, weak_table.upgrade()) {
+                table.borrow_mut().remove(id);
+            }
+        });
+        Self {
+            registry,
+            table,
+            key_fn: Box::new(key_fn),
+            _subscription: subscription,
+        }
+    }
+
+    /// Return the live interned object with the given content, or create it.
+    ///
+    /// The parents are registered in the registry of the interner.
+    ///
+    /// Panics if the key strategy of the registry cannot assign a key to the
+    /// object or one of its parents, see [`Interner::try_intern`].
+    pub fn intern(
+        &self,
+        value: N,
+        parents: impl IntoIterator<Item = (RelRc<N, E>, E)>,
+    ) -> RelRc<N, E> {
+        self.try_intern(value, parents)
+            .expect("the key strategy could not assign a key to the node")
+    }
+
+    /// Return the live interned object with the given content, or create it.
+    ///
+    /// Returns `None` if the key strategy of the registry cannot assign a key
+    /// to the object or one of its parents. See [`Interner::intern`].
+    pub fn try_intern(
+        &self,
+        value: N,
+        parents: impl IntoIterator<Item = (RelRc<N, E>, E)>,
+    ) -> Option<RelRc<N, E>> {
+        let parents = parents.into_iter().collect_vec();
+        let key = {
+            let parent_ids: Vec<_> = parents
+                .iter()
+                .map(|(parent, edge)| Some((parent.try_register_in(&self.registry)?, edge)))
+                .collect::<Option<_>>()?;
+            (self.key_fn)(&value, &parent_ids)
+        };
+        if let Some(node) = self.get_by_key(&key) {
+            return Some(node);
+        }
+        let node = RelRc::with_parents(value, parents);
+        let id = node.try_register_in(&self.registry)?;
+        let mut table = self.table.borrow_mut();
+        table.by_key.insert(key.clone(), id);
+        table.by_id.insert(id, key);
+        Some(node)
+    }
+
+    /// The live interned object with the given key, if any.
+    pub fn get_by_key(&self, key: &K) -> Option<RelRc<N, E>> {
+        let id = *self.table.borrow().by_key.get(key)?;
+        self.registry.borrow().get(id)
+    }
+
+    /// The registry of the interner.
+    pub fn registry(&self) -> &Rc<RefCell<Registry<N, E, S>>> {
+        &self.registry
+    }
+
+    /// The number of interned objects.
+    pub fn len(&self) -> usize {
+        self.table.borrow().by_key.len()
+    }
+
+    /// Whether there are no interned objects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<N, E, S: KeyStrategy, K> fmt::Debug for Interner<N, E, S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.table.borrow().by_key.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<N: 'static, E: 'static> RelRc<N, E> {
+    /// Create a new [`RelRc<N, E>`] with the given list of parent objects, or
+    /// return the live object of `interner` with the same content.
+    ///
+    /// See [`Interner::intern`].
+    pub fn with_parents_interned<S: KeyStrategy, K: Clone + Hash + Eq + 'static>(
+        value: N,
+        parents: impl IntoIterator<Item = (RelRc<N, E>, E)>,
+        interner: &Interner<N, E, S, K>,
+    ) -> Self {
+        interner.intern(value, parents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{SequentialKeys, SuppliedKeys};
+
+    #[test]
+    fn test_interned_nodes() {
+        let interner = Interner::<&str, usize>::new();
+        let root = RelRc::with_parents_interned("root", [], &interner);
+        let child = RelRc::with_parents_interned("child", [(root.clone(), 1)], &interner);
+
+        let same_root = RelRc::with_parents_interned("root", [], &interner);
+        let same_child = RelRc::with_parents_interned("child", [(same_root, 1)], &interner);
+        let other_edge = RelRc::with_parents_interned("child", [(root.clone(), 2)], &interner);
+        assert!(RelRc::ptr_eq(&child, &same_child));
+        assert!(!RelRc::ptr_eq(&child, &other_edge));
+        assert_eq!(interner.len(), 3);
+
+        // Entries are released when nodes are dropped
+        drop((child, same_child));
+        assert_eq!(interner.len(), 2);
+        let new_child = RelRc::with_parents_interned("child", [(root.clone(), 1)], &interner);
+        assert_eq!(new_child.n_registries(), 1);
+        drop((root, new_child, other_edge));
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn test_custom_key() {
+        // Intern by value only, ignoring parents
+        let interner = Interner::with_key(Registry::<String, ()>::new(), |value: &String, _| {
+            value.to_lowercase()
+        });
+        let root = interner.intern("Root".to_string(), []);
+        let child = interner.intern("child".to_string(), [(root.clone(), ())]);
+        let same_child = interner.intern("CHILD".to_string(), []);
+        assert!(RelRc::ptr_eq(&child, &same_child));
+        assert_eq!(same_child.value(), "child");
+        assert!(interner.get_by_key(&"root".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_key_strategy() {
+        let interner = Interner::with_registry(Registry::<&str, (), _>::with_keys(SequentialKeys));
+        let root = interner.intern("root", []);
+        let child = interner.intern("child", [(root.clone(), ())]);
+        let same_child = interner.intern("child", [(root.clone(), ())]);
+        assert!(RelRc::ptr_eq(&child, &same_child));
+        assert!(interner.get_by_key(&("child", vec![(0, ())])).is_some());
+
+        let supplied = Interner::with_registry(Registry::<&str, (), _>::with_keys(SuppliedKeys::<
+            &str,
+        >::default(
+        )));
+        assert!(supplied.try_intern("root", []).is_none());
+        assert!(supplied.is_empty());
+    }
+}
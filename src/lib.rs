@@ -1,20 +1,33 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
+mod child_index;
+mod common_ancestors;
 // pub mod detached;
 pub mod edge;
+pub mod hash_id;
 pub mod history;
+pub mod identity;
+pub mod intern;
 pub mod node;
 #[cfg(feature = "petgraph")]
 pub mod petgraph;
+pub mod pointer;
 pub mod registry;
+pub mod retention;
 // pub mod resolver;
 pub mod serialization;
+pub mod sync;
+pub mod traversal;
 
-pub use edge::Edge;
-pub use history::{EdgeId, HistoryGraph};
+pub use edge::{Edge, EdgeSource};
+pub use hash_id::RelRcHash;
+pub use history::{CausalOrder, EdgeId, HistoryGraph};
+pub use identity::{ByCreation, ByPtr};
+pub use intern::Interner;
 pub use node::RelRc;
 pub use registry::{NodeId, Registry};
+pub use sync::{RelArc, RelArcWeak};
 
 // #[cfg(feature = "mpi")]
 // pub use detached::mpi;
@@ -1,8 +1,10 @@
 //! Reference-counted pointers.
 
-use std::cell::Ref;
 use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
 use std::iter;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{self, AtomicU64};
 use std::{
     cell::RefCell,
     ops::Deref,
@@ -12,9 +14,15 @@ use std::{
 use derive_more::From;
 use derive_where::derive_where;
 use fxhash::FxHashSet;
+use itertools::Itertools;
 
+use crate::child_index::{ChildIndex, ChildIndexSlot, KeyedChildIndex, OutgoingKey};
+use crate::hash_id::RelRcHash;
+use crate::registry::{dispatch_events, remove_dropped, KeyStrategy, RegistryLink};
 use crate::Registry;
-use crate::{edge::InnerEdgeData, Edge, WeakEdge};
+use crate::{edge::InnerEdgeData, Edge, EdgeSource, WeakEdge};
+
+pub use crate::child_index::NotIndexed;
 
 /// A single-threaded reference-counted pointer, optionally with relationships
 /// to other [`RelRc`] objects.
@@ -35,16 +43,23 @@ use crate::{edge::InnerEdgeData, Edge, WeakEdge};
 ///
 /// ## Unique IDs
 ///
-/// Every [`RelRc`] object is assigned a unique hash-based identifier. For this
-/// reason, object creation operations will require N and E generics to be
-/// hashable.
+/// [`RelRc`] objects created with the hashed constructors, such as
+/// [`RelRc::with_parents_hashed`], are assigned a content-addressed
+/// identifier, see [`InnerData::hash_id`]. These constructors require N and E
+/// generics to be hashable.
+///
+/// ## Deep histories
+///
+/// Dropping a [`RelRc`] releases its ancestors iteratively rather than
+/// recursively, so arbitrarily long chains of nodes can be freed without
+/// overflowing the stack.
 #[derive(Debug)]
 #[derive_where(Clone)]
-pub struct RelRc<N, E>(Rc<InnerData<N, E>>);
+pub struct RelRc<N, E>(ManuallyDrop<Rc<InnerData<N, E>>>);
 
 impl<N, E> From<Rc<InnerData<N, E>>> for RelRc<N, E> {
     fn from(inner: Rc<InnerData<N, E>>) -> Self {
-        Self(inner)
+        Self(ManuallyDrop::new(inner))
     }
 }
 
@@ -61,18 +76,48 @@ impl<N, E> RelRc<N, E> {
     /// corresponding edge value. The order of the parents is guaranteed to
     /// never change.
     pub fn with_parents(value: N, parents: impl IntoIterator<Item = (RelRc<N, E>, E)>) -> Self {
+        Self::with_sources(
+            value,
+            parents
+                .into_iter()
+                .map(|(parent, edge_value)| (EdgeSource::Strong(parent), edge_value)),
+        )
+    }
+
+    /// Create a new [`RelRc<N, E>`] with the given list of edge sources.
+    ///
+    /// Like [`RelRc::with_parents`], but every parent may be referenced either
+    /// strongly or weakly. Weak edges record a dependency on a parent without
+    /// keeping it alive: once the parent has been dropped, the edge is
+    /// dangling, see [`InnerEdgeData::is_dangling`].
+    pub fn with_sources(
+        value: N,
+        sources: impl IntoIterator<Item = (EdgeSource<N, E>, E)>,
+    ) -> Self {
+        Self::with_sources_and_hash(value, sources, |_, _| None)
+    }
+
+    /// Create a new [`RelRc<N, E>`] with the given list of edge sources and
+    /// the hash computed by `hash` from the value and incoming edges.
+    pub(crate) fn with_sources_and_hash(
+        value: N,
+        sources: impl IntoIterator<Item = (EdgeSource<N, E>, E)>,
+        hash: impl FnOnce(&N, &[InnerEdgeData<N, E>]) -> Option<RelRcHash>,
+    ) -> Self {
         let inner = Rc::new_cyclic(|weak_node| {
             let weak_node: RelWeak<N, E> = weak_node.clone().into();
-            let incoming = parents
+            let incoming = sources
                 .into_iter()
-                .map(|(parent, edge_value)| {
-                    InnerEdgeData::new(edge_value, parent, weak_node.clone())
+                .map(|(source, edge_value)| {
+                    InnerEdgeData::new(edge_value, source, weak_node.clone())
                 })
                 .collect();
-            InnerData::with_incoming(value, incoming)
+            let mut data = InnerData::with_incoming(value, incoming);
+            data.hash_id = hash(&data.value, &data.incoming);
+            data
         });
         let node = Self::from(inner);
-        register_outgoing_edges(&node.incoming);
+        register_outgoing_edges(&node);
         node
     }
 }
@@ -100,88 +145,262 @@ impl<N, E> RelRc<N, E> {
 
     /// Register this node in the given registry and return its ID.
     ///
-    /// A node can only be registered in one registry at a time. If the node is
-    /// already registered in a different registry, this will return `None`.
+    /// A node may be registered in any number of registries, with a distinct
+    /// ID in each of them. If the node is already registered in `registry`,
+    /// its existing ID is returned.
     ///
-    /// The node ID in the registry will be freed when the last reference to
+    /// The node ID will be freed in every registry when the last reference to
     /// `self` is dropped.
-    #[must_use]
-    pub fn try_register_in(
+    ///
+    /// Panics if the key strategy of the registry cannot assign a key to the
+    /// node, see [`RelRc::try_register_in`].
+    pub fn register_in<S: KeyStrategy>(&self, registry: &Rc<RefCell<Registry<N, E, S>>>) -> S::Key
+    where
+        N: 'static,
+        E: 'static,
+    {
+        self.try_register_in(registry)
+            .expect("the key strategy could not assign a key to the node")
+    }
+
+    /// Register this node in the given registry and return its ID.
+    ///
+    /// Returns `None` if the key strategy of the registry cannot assign a key
+    /// to the node, e.g. if keys must be supplied using
+    /// [`RelRc::try_register_with_key`]. See [`RelRc::register_in`].
+    pub fn try_register_in<S: KeyStrategy>(
         &self,
-        registry: &Rc<RefCell<Registry<N, E>>>,
-    ) -> Option<crate::registry::NodeId> {
-        if !self.try_set_register(registry) {
-            return None;
+        registry: &Rc<RefCell<Registry<N, E, S>>>,
+    ) -> Option<S::Key>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        let id = registry.borrow_mut().try_add_node(self);
+        self.track_registry(registry, id)
+    }
+
+    /// Register this node in the given registry with the given key.
+    ///
+    /// Returns `None` if the key is in use by another node, if the node is
+    /// already registered in `registry` with a different key, or if the key
+    /// strategy of the registry does not accept supplied keys. See
+    /// [`RelRc::register_in`].
+    pub fn try_register_with_key<S: KeyStrategy>(
+        &self,
+        registry: &Rc<RefCell<Registry<N, E, S>>>,
+        key: S::Key,
+    ) -> Option<S::Key>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        let id = registry.borrow_mut().try_add_node_with_key(self, key);
+        self.track_registry(registry, id)
+    }
+
+    /// Record that the node is tracked by `registry`, if it was registered.
+    fn track_registry<S: KeyStrategy>(
+        &self,
+        registry: &Rc<RefCell<Registry<N, E, S>>>,
+        id: Option<S::Key>,
+    ) -> Option<S::Key>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        Registry::dispatch_events(registry);
+        id?;
+        let mut registries = self.0.registries.borrow_mut();
+        // Registries that have been dropped no longer track the node
+        registries.retain(|r| r.is_alive());
+        if !registries.iter().any(|r| r.points_to(registry)) {
+            registries.push(RegistryLink::new(registry));
         }
-        Some(registry.borrow_mut().add_node(self))
+        id
     }
 
-    /// Get the registry that this node is registered in, if there is one.
-    pub fn registry(&self) -> Option<Rc<RefCell<Registry<N, E>>>> {
+    /// Stop tracking this node in the given registry.
+    ///
+    /// The node must have been removed from `registry` beforehand.
+    pub(crate) fn unregister_from<S: KeyStrategy>(
+        &self,
+        registry: &Rc<RefCell<Registry<N, E, S>>>,
+    ) {
         self.0
-            .registry
+            .registries
+            .borrow_mut()
+            .retain(|r| r.is_alive() && !r.points_to(registry));
+    }
+
+    /// Get all live registries with key strategy `S` that this node is
+    /// registered in, in the order in which the node was registered.
+    pub fn registries<S: KeyStrategy>(&self) -> Vec<Rc<RefCell<Registry<N, E, S>>>>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        self.0
+            .registries
             .borrow()
-            .as_ref()
-            .and_then(|weak| weak.upgrade())
+            .iter()
+            .filter_map(|link| link.downcast())
+            .collect()
     }
 
-    /// Set the registry that tracks this node.
+    /// Get the number of live registries that this node is registered in.
+    pub fn n_registries(&self) -> usize {
+        let registries = self.0.registries.borrow();
+        registries.iter().filter(|r| r.is_alive()).count()
+    }
+
+    /// Get the first live registry that this node is registered in, if there
+    /// is one.
+    #[deprecated(note = "nodes may be registered in several registries, use `RelRc::registries`")]
+    pub fn registry(&self) -> Option<Rc<RefCell<Registry<N, E>>>>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        self.registries().into_iter().next()
+    }
+
+    /// Register a callback to be called when the node is dropped.
     ///
-    /// A node can only be registered in one registry at a time. If the node is
-    /// already registered in a different registry, this will return `None`.
+    /// The callback is called exactly once, when the last reference to the
+    /// node (and thus to all its descendants) disappears. It receives the
+    /// value of the node and the values of its incoming edges. See
+    /// [`Registry::on_drop`] to observe all nodes of a registry.
     ///
-    /// The node ID in the registry will be freed when the last reference to
-    /// `self` is dropped.
-    #[must_use]
-    fn try_set_register(&self, registry: &Rc<RefCell<Registry<N, E>>>) -> bool {
-        let weak = Rc::downgrade(registry);
-        if let Some(existing) = self.0.registry.borrow().as_ref() {
-            return existing.ptr_eq(&weak);
-        }
-        self.0.registry.borrow_mut().replace(weak);
-        true
+    /// Callbacks are called after the node has been removed from its
+    /// registries, but before its parents are released. Registries that are
+    /// borrowed when the node is dropped remove it once they are released,
+    /// see [`Registry::dispatch_events`]. No internal borrows
+    /// are held while callbacks run: they may create, register and drop other
+    /// nodes. Nodes dropped by a callback are released before the callback
+    /// returns.
+    pub fn on_drop(&self, observer: impl FnOnce(&N, &[&E]) + 'static) {
+        self.0.observers.borrow_mut().0.push(Box::new(observer));
     }
 
     /// Iterate over all ancestors of the object, including self.
+    ///
+    /// Ancestors are visited in breadth-first order. Use
+    /// [`RelRc::traverse_ancestors`] for other traversal orders.
     pub fn all_ancestors(&self) -> impl Iterator<Item = &RelRc<N, E>> + '_ {
-        let mut seen = FxHashSet::default();
-        let mut stack = VecDeque::from([self]);
+        self.traverse_ancestors().into_iter().map(|(node, _)| node)
+    }
+}
 
-        iter::from_fn(move || {
-            let node = stack.pop_front()?;
-            if !seen.insert(node.as_ptr()) {
-                return None;
-            }
-            stack.extend(node.all_parents());
-            Some(node)
-        })
+impl<N, E> RelRc<N, E> {
+    /// Return the node value, along with its parents and incoming edge values,
+    /// if `self` is the only strong reference to the node.
+    ///
+    /// Otherwise, an [`Err`] is returned with the same [`RelRc`] that was
+    /// passed in. This mirrors [`Rc::try_unwrap`]. A node with live children
+    /// along strong edges is never uniquely owned, as these children hold a
+    /// reference to it. Children along weak edges do not: the node may be
+    /// unwrapped, after which their edges are dangling, see
+    /// [`InnerEdgeData::is_dangling`].
+    ///
+    /// The parents are returned in the same format as expected by
+    /// [`RelRc::with_sources`]. On success, the node is removed from its
+    /// registries and from the outgoing edges of its parents. Its drop
+    /// observers are not called, as the value is not dropped.
+    pub fn try_unwrap(self) -> Result<NodeParts<N, E>, Self> {
+        let inner = self.into_rc();
+        let ptr = Rc::as_ptr(&inner);
+        let data = Rc::try_unwrap(inner).map_err(RelRc::from)?;
+        Ok(data.into_parts(ptr))
+    }
+
+    /// Return the node value, along with its parents and incoming edge values,
+    /// if `self` is the only strong reference to the node.
+    ///
+    /// Otherwise, [`None`] is returned and the reference is dropped. This
+    /// mirrors [`Rc::into_inner`]; see [`RelRc::try_unwrap`] for details.
+    pub fn into_inner(self) -> Option<NodeParts<N, E>> {
+        let inner = self.into_rc();
+        let ptr = Rc::as_ptr(&inner);
+        let data = Rc::into_inner(inner)?;
+        Some(data.into_parts(ptr))
+    }
+
+    /// Consume the handle and return the underlying `Rc`, without running
+    /// [`Drop`] for [`RelRc`].
+    fn into_rc(self) -> Rc<InnerData<N, E>> {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so `this.0` is taken exactly once.
+        unsafe { ManuallyDrop::take(&mut this.0) }
     }
 }
 
 impl<N, E> Drop for RelRc<N, E> {
     fn drop(&mut self) {
-        if Rc::strong_count(&self.0) > 1 {
-            return;
-        }
-        let mut register = self.0.registry.borrow_mut();
-        let Some(weak) = register.take() else {
-            return;
-        };
-        let Some(registry) = weak.upgrade() else {
-            return;
-        };
-        let Some(id) = registry.borrow().get_id(self) else {
-            return;
+        // SAFETY: `self.0` is not accessed again after being taken.
+        let inner = unsafe { ManuallyDrop::take(&mut self.0) };
+        release(inner);
+    }
+}
+
+/// Drop a strong reference to a node.
+///
+/// If it is the last strong reference, the node is unregistered, its drop
+/// observers are called and its incoming edges are dropped, releasing in turn
+/// the references to its parents. This is done with an explicit worklist
+/// rather than by recursion, to support arbitrarily deep histories.
+fn release<N, E>(inner: Rc<InnerData<N, E>>) {
+    let mut worklist = vec![inner];
+    while let Some(inner) = worklist.pop() {
+        let ptr = Rc::as_ptr(&inner);
+        let Some(data) = Rc::into_inner(inner) else {
+            continue;
         };
-        registry.borrow_mut().remove(id);
+        unregister_outgoing_edges(&data);
+        let node_observers = data.observers.into_inner().0;
+        let mut registry_observers = Vec::new();
+        let registries = data
+            .registries
+            .into_inner()
+            .into_iter()
+            .filter_map(|link| {
+                registry_observers.extend(link.drop_tracker()?.node_dropped(ptr));
+                link.upgrade()
+            })
+            .collect_vec();
+        // Registries that are borrowed remove the node once they are released
+        for registry in &registries {
+            remove_dropped(registry);
+        }
+        // Call the observers once all borrows have been released
+        if !node_observers.is_empty() || !registry_observers.is_empty() {
+            let edge_values = data.incoming.iter().map(|e| e.value()).collect_vec();
+            for observer in node_observers {
+                observer(&data.value, &edge_values);
+            }
+            for observer in registry_observers {
+                observer(&data.value, &edge_values);
+            }
+        }
+        for registry in &registries {
+            dispatch_events(registry);
+        }
+        worklist.extend(
+            data.incoming
+                .into_iter()
+                .filter_map(|e| e.source.into_strong())
+                .map(|source| source.into_rc()),
+        );
     }
 }
 
 /// A weak reference to a [`RelRc`] object.
 ///
 /// Upgrades to [`RelRc`] if the reference is valid.
+///
+/// The default [`RelWeak`] never upgrades, like [`Weak::new`].
 #[derive(Debug, From)]
-#[derive_where(Clone)]
+#[derive_where(Clone, Default)]
 pub struct RelWeak<N, E>(Weak<InnerData<N, E>>);
 
 impl<N, E> RelWeak<N, E> {
@@ -201,10 +420,34 @@ impl<N, E> RelWeak<N, E> {
     pub fn as_ptr(&self) -> *const InnerData<N, E> {
         Weak::as_ptr(&self.0)
     }
+
+    /// The number of strong references to the underlying data.
+    pub fn strong_count(&self) -> usize {
+        self.0.strong_count()
+    }
+}
+
+/// Weak references are compared by identity: two references are equal if
+/// they point to the same object, whether or not it is still alive.
+impl<N, E> PartialEq for RelWeak<N, E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
 }
 
+impl<N, E> Eq for RelWeak<N, E> {}
+
+impl<N, E> Hash for RelWeak<N, E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ptr().hash(state);
+    }
+}
+
+/// The value of a node, along with its edge sources and incoming edge values.
+type NodeParts<N, E> = (N, Vec<(EdgeSource<N, E>, E)>);
+
 /// A weak reference to a [`Registry`] object.
-pub type WeakRegistry<N, E> = Weak<RefCell<Registry<N, E>>>;
+pub type WeakRegistry<N, E, S = crate::registry::SlotKeys> = Weak<RefCell<Registry<N, E, S>>>;
 
 /// Data within a [`RelRc`] object.
 ///
@@ -220,13 +463,133 @@ pub struct InnerData<N, E> {
     ///
     /// The ordering and position of the incoming edges is immutable.
     incoming: Vec<InnerEdgeData<N, E>>,
+    /// The generation of the object, see [`InnerData::generation`].
+    generation: usize,
+    /// The creation order of the object, see [`InnerData::creation_order`].
+    creation_order: CreationOrder,
+    /// The content-addressed hash of the object, see [`InnerData::hash_id`].
+    hash_id: Option<RelRcHash>,
     /// The outgoing edges from the object (weak references).
     ///
-    /// The order and position of the outgoing edges may change at any time, as
-    /// the edges may get deleted.
-    outgoing: RefCell<Vec<WeakEdge<N, E>>>,
-    /// The registry that tracks this node, if there is one.
-    registry: RefCell<Option<WeakRegistry<N, E>>>,
+    /// The edges are ordered by creation of their targets. Edges are removed
+    /// when their target is dropped.
+    outgoing: RefCell<OutgoingEdges<N, E>>,
+    /// The registries that track this node.
+    registries: RefCell<Vec<RegistryLink<N, E>>>,
+    /// Callbacks to call when the node is dropped.
+    observers: RefCell<DropObservers<N, E>>,
+}
+
+/// The outgoing edges of an object, in the creation order of their targets.
+///
+/// Every edge is keyed by the creation order of its target and its position
+/// in the target's incoming edges, which only increase as edges are added.
+/// When a target is dropped, its edges are marked as removed in place, found
+/// by binary search. Removed edges are reclaimed once they make up more than
+/// half of the list, keeping removals amortised constant time.
+///
+/// The edges may additionally be indexed by edge value, see
+/// [`InnerData::index_children_by`].
+#[derive(Debug)]
+#[derive_where(Clone, Default)]
+struct OutgoingEdges<N, E> {
+    /// The edges with their keys, sorted by key.
+    edges: Vec<(OutgoingKey, Option<WeakEdge<N, E>>)>,
+    /// The number of removed edges in `edges`.
+    n_removed: usize,
+    /// The index of the edges by edge value, if there is one.
+    index: ChildIndexSlot<E>,
+}
+
+impl<N, E> OutgoingEdges<N, E> {
+    fn push(&mut self, key: OutgoingKey, edge: WeakEdge<N, E>, value: &E) {
+        debug_assert!(self.edges.last().map_or(true, |&(last, _)| last < key));
+        self.edges.push((key, Some(edge)));
+        if let Some(index) = self.index.0.as_mut() {
+            index.insert(value, key);
+        }
+    }
+
+    fn remove(&mut self, key: OutgoingKey, value: &E) {
+        if let Some(index) = self.index.0.as_mut() {
+            index.remove(value, key);
+        }
+        let Ok(pos) = self.edges.binary_search_by_key(&key, |&(k, _)| k) else {
+            return;
+        };
+        if self.edges[pos].1.take().is_some() {
+            self.n_removed += 1;
+        }
+        if 2 * self.n_removed > self.edges.len() {
+            self.edges
+                .retain(|(_, e)| e.as_ref().is_some_and(|e| e.target.strong_count() > 0));
+            self.n_removed = 0;
+        }
+    }
+
+    /// The edge with the given key, if its target is still alive.
+    fn get(&self, key: OutgoingKey) -> Option<&WeakEdge<N, E>> {
+        let pos = self.edges.binary_search_by_key(&key, |&(k, _)| k).ok()?;
+        self.edges[pos]
+            .1
+            .as_ref()
+            .filter(|e| e.target.strong_count() > 0)
+    }
+
+    /// The edges whose target is still alive, with their keys.
+    fn iter_keyed(&self) -> impl Iterator<Item = (OutgoingKey, &WeakEdge<N, E>)> {
+        self.edges
+            .iter()
+            .filter_map(|(k, e)| Some((*k, e.as_ref()?)))
+            .filter(|(_, e)| e.target.strong_count() > 0)
+    }
+
+    /// The edges whose target is still alive.
+    fn iter(&self) -> impl Iterator<Item = &WeakEdge<N, E>> {
+        self.iter_keyed().map(|(_, e)| e)
+    }
+}
+
+/// The number of objects created so far, used to assign creation orders.
+static N_CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// A unique number assigned to every object at creation, in increasing order.
+///
+/// Cloning an [`InnerData`] creates a new object, with a new creation order.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct CreationOrder(u64);
+
+impl Default for CreationOrder {
+    fn default() -> Self {
+        Self(N_CREATED.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+}
+
+impl Clone for CreationOrder {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// A node drop callback, see [`RelRc::on_drop`].
+type DropObserver<N, E> = Box<dyn FnOnce(&N, &[&E])>;
+
+/// The drop callbacks registered on a node.
+///
+/// Callbacks belong to a node and are not cloned along with [`InnerData`].
+#[derive_where(Default)]
+struct DropObservers<N, E>(Vec<DropObserver<N, E>>);
+
+impl<N, E> Clone for DropObservers<N, E> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<N, E> std::fmt::Debug for DropObservers<N, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DropObservers").field(&self.0.len()).finish()
+    }
 }
 
 impl<N, E> Deref for RelRc<N, E> {
@@ -242,8 +605,12 @@ impl<N: Default, E> Default for InnerData<N, E> {
         Self {
             value: Default::default(),
             incoming: Vec::new(),
-            outgoing: RefCell::new(Vec::new()),
-            registry: RefCell::new(None),
+            generation: 0,
+            creation_order: Default::default(),
+            hash_id: None,
+            outgoing: Default::default(),
+            registries: Default::default(),
+            observers: Default::default(),
         }
     }
 }
@@ -259,20 +626,56 @@ impl<N, E> InnerData<N, E> {
         Self {
             value,
             incoming: Vec::new(),
-            outgoing: RefCell::new(Vec::new()),
-            registry: RefCell::new(None),
+            generation: 0,
+            creation_order: Default::default(),
+            hash_id: None,
+            outgoing: Default::default(),
+            registries: Default::default(),
+            observers: Default::default(),
         }
     }
 
     pub(crate) fn with_incoming(value: N, incoming: Vec<InnerEdgeData<N, E>>) -> Self {
+        let generation = incoming
+            .iter()
+            .filter_map(|e| e.upgrade_source())
+            .map(|parent| parent.generation + 1)
+            .max()
+            .unwrap_or(0);
         Self {
             value,
             incoming,
-            outgoing: RefCell::new(Vec::new()),
-            registry: RefCell::new(None),
+            generation,
+            creation_order: Default::default(),
+            hash_id: None,
+            outgoing: Default::default(),
+            registries: Default::default(),
+            observers: Default::default(),
         }
     }
 
+    /// Detach the data of a node that is no longer referenced from its
+    /// registry and its parents, and return its value and incoming edges.
+    ///
+    /// `ptr` is the address that the data had within its `Rc`.
+    fn into_parts(self, ptr: *const Self) -> NodeParts<N, E> {
+        unregister_outgoing_edges(&self);
+        for link in self.registries.into_inner() {
+            if let Some(drop_tracker) = link.drop_tracker() {
+                drop_tracker.node_dropped(ptr);
+            }
+            if let Some(registry) = link.upgrade() {
+                dispatch_events(&registry);
+            }
+        }
+        let parents = self
+            .incoming
+            .into_iter()
+            .map(|InnerEdgeData { value, source, .. }| (source, value))
+            .collect();
+        (self.value, parents)
+    }
+
     /// The i-th incoming edge to the node.
     pub fn incoming(&self, index: usize) -> Option<&InnerEdgeData<N, E>> {
         self.incoming.get(index)
@@ -286,8 +689,43 @@ impl<N, E> InnerData<N, E> {
     }
 
     /// The i-th parent of the object.
+    ///
+    /// Returns `None` if the i-th incoming edge is weak, see
+    /// [`InnerEdgeData::upgrade_source`].
     pub fn parent(&self, index: usize) -> Option<&RelRc<N, E>> {
-        self.incoming.get(index).map(|e| e.source())
+        self.incoming.get(index)?.source()
+    }
+
+    /// The generation of the object, i.e. its distance from the roots.
+    ///
+    /// Roots have generation 0; every other object has a generation one more
+    /// than the maximum generation of its parents when it was created,
+    /// including the sources of weak edges that were alive at the time. The
+    /// generation thus strictly increases along every edge that is not
+    /// dangling.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// The creation order of the object.
+    ///
+    /// Every object is assigned a unique number when it is created, and
+    /// objects created later have larger numbers. Parents are thus always
+    /// created before their children. See [`ByCreation`] to order objects by
+    /// creation.
+    ///
+    /// [`ByCreation`]: crate::ByCreation
+    pub fn creation_order(&self) -> u64 {
+        self.creation_order.0
+    }
+
+    /// The content-addressed hash of the object, if it was created with one
+    /// of the hashed constructors, such as [`RelRc::with_parents_hashed`].
+    ///
+    /// The hash covers the value of the object, the values of its incoming
+    /// edges and the hashes of its parents.
+    pub fn hash_id(&self) -> Option<RelRcHash> {
+        self.hash_id
     }
 
     /// The value of the object, also obtainable with [`Deref`].
@@ -308,24 +746,23 @@ impl<N, E> InnerData<N, E> {
             .map(|(i, e)| WeakEdge::new(i, e.target.clone()))
     }
 
-    /// All outgoing edges as weak references.
-    ///
-    /// This makes a borrow to the underlying `RefCell`, meaning that no
-    /// new RelRc objects can be created for as long as the Ref is in scope.
-    ///
-    /// Don't expose publicly to avoid this issue.
-    pub(crate) fn all_outgoing_weak_ref(&self) -> Ref<'_, [WeakEdge<N, E>]> {
-        Ref::map(self.outgoing.borrow(), |edges| edges.as_slice())
-    }
-
-    /// All outgoing edges as weak references.
+    /// All outgoing edges as weak references, in the creation order of their
+    /// targets.
     pub fn all_outgoing_weak(&self) -> Vec<WeakEdge<N, E>> {
-        self.all_outgoing_weak_ref().to_vec()
+        self.outgoing.borrow().iter().cloned().collect()
     }
 
     /// Iterate over all parents of the object.
-    pub fn all_parents(&self) -> impl ExactSizeIterator<Item = &RelRc<N, E>> {
-        self.all_incoming().iter().map(|e| e.source())
+    ///
+    /// Only the sources of strong edges are returned.
+    pub fn all_parents(&self) -> impl Iterator<Item = &RelRc<N, E>> {
+        self.all_incoming().iter().filter_map(|e| e.source())
+    }
+
+    /// Iterate over all dangling incoming edges, i.e. weak edges whose source
+    /// has been dropped.
+    pub fn all_dangling(&self) -> impl Iterator<Item = &InnerEdgeData<N, E>> {
+        self.all_incoming().iter().filter(|e| e.is_dangling())
     }
 
     /// The number of incoming edges.
@@ -335,49 +772,182 @@ impl<N, E> InnerData<N, E> {
 }
 
 impl<N, E> InnerData<N, E> {
-    /// Iterate over all outgoing edges.
+    /// Iterate over all outgoing edges, in the creation order of their
+    /// targets.
     ///
     /// The edges are weakly referenced, so they may get deleted if all
     /// downstream nodes have been deleted.
     ///
-    /// This upgrades all outgoing edges, removes references to edges that have
-    /// been deleted, and returns the remaining edges in a new vector. This is
-    /// not done lazily to limit mutable borrow of the outgoing RefCell.
+    /// This upgrades all outgoing edges and returns them in a new vector. This
+    /// is not done lazily to avoid holding a borrow of the outgoing edges,
+    /// which are modified when children are created or dropped.
     pub fn all_outgoing(&self) -> Vec<Edge<N, E>> {
-        let mut edges = Vec::with_capacity(self.outgoing.borrow().len());
-        self.outgoing.borrow_mut().retain(|e| {
-            if let Some(edge) = e.upgrade() {
-                edges.push(edge);
-                true
-            } else {
-                false
-            }
-        });
-        edges
+        self.outgoing
+            .borrow()
+            .iter()
+            .filter_map(|e| e.upgrade())
+            .collect()
     }
 
-    /// Iterate over all children of the object.
+    /// Iterate over all children of the object, in creation order.
     ///
-    /// The children are the objects that have an incoming edge from the object.
+    /// The children are the objects that have an incoming edge from the object,
+    /// whether strong or weak. A child with several edges from the object is
+    /// returned once per edge.
     pub fn all_children(&self) -> impl ExactSizeIterator<Item = RelRc<N, E>> {
         self.all_outgoing().into_iter().map(|e| e.into_target())
     }
 
     /// The number of outgoing edges.
     pub fn n_outgoing(&self) -> usize {
-        self.all_outgoing().len()
+        self.outgoing.borrow().iter().count()
+    }
+
+    /// Iterate over all descendants of the object, excluding self.
+    ///
+    /// Descendants are visited in breadth-first order. Outgoing edges are
+    /// upgraded lazily as the iterator advances; descendants that have been
+    /// dropped in the meantime are skipped.
+    pub fn all_descendants(&self) -> impl Iterator<Item = RelRc<N, E>> {
+        let mut seen = FxHashSet::default();
+        let mut queue = VecDeque::from(self.outgoing_targets());
+
+        iter::from_fn(move || loop {
+            let weak = queue.pop_front()?;
+            if !seen.insert(weak.as_ptr()) {
+                continue;
+            }
+            let Some(node) = weak.upgrade() else {
+                continue;
+            };
+            queue.extend(node.outgoing_targets());
+            return Some(node);
+        })
+    }
+
+    /// Iterate over all descendants of the object in topological order,
+    /// excluding self.
+    ///
+    /// Every descendant is returned after all of its parents that are
+    /// descendants of self. Descendants that are no longer alive are skipped.
+    ///
+    /// Unlike [`InnerData::all_descendants`], the set of descendants is
+    /// computed eagerly, as the order depends on all of them.
+    pub fn all_descendants_topological(&self) -> impl Iterator<Item = RelRc<N, E>> {
+        // Reverse DFS post-order, with an explicit stack of unvisited children
+        let mut seen = FxHashSet::default();
+        let mut post_order = Vec::new();
+        let mut stack = vec![(None, self.outgoing_targets())];
+        while let Some((_, children)) = stack.last_mut() {
+            if let Some(child) = children.pop() {
+                if !seen.insert(child.as_ptr()) {
+                    continue;
+                }
+                if let Some(child) = child.upgrade() {
+                    let grandchildren = child.outgoing_targets();
+                    stack.push((Some(child), grandchildren));
+                }
+            } else {
+                let (node, _) = stack.pop().expect("non-empty stack");
+                post_order.extend(node);
+            }
+        }
+        post_order.into_iter().rev()
+    }
+
+    /// Index the outgoing edges of the object by a key computed from their
+    /// edge values, for lookups with [`InnerData::child_by_edge`].
+    ///
+    /// The index is kept up to date as children are created and dropped. It
+    /// replaces any existing index of the object. `key_fn` is called while the
+    /// outgoing edges of the object are borrowed: it must not create or drop
+    /// children of the object.
+    pub fn index_children_by<K: Hash + Eq + 'static>(&self, key_fn: impl Fn(&E) -> K + 'static)
+    where
+        E: 'static,
+    {
+        let mut index = KeyedChildIndex::new(key_fn);
+        // Upgrade the edges first, so that no borrow is held if they are dropped
+        let edges = self
+            .outgoing
+            .borrow()
+            .iter_keyed()
+            .filter_map(|(key, e)| Some((key, e.upgrade()?)))
+            .collect_vec();
+        for (key, edge) in &edges {
+            index.insert(edge.value(), *key);
+        }
+        self.outgoing.borrow_mut().index = ChildIndexSlot(Some(Box::new(index)));
+        drop(edges);
+    }
+
+    /// The first outgoing edge, in the creation order of the children, whose
+    /// key is `key`.
+    ///
+    /// Returns `Ok(None)` if there is no such edge. Returns an error if the
+    /// outgoing edges have not been indexed by keys of type `K` using
+    /// [`InnerData::index_children_by`].
+    pub fn child_by_edge<K: Hash + Eq + 'static>(
+        &self,
+        key: &K,
+    ) -> Result<Option<Edge<N, E>>, NotIndexed>
+    where
+        E: 'static,
+    {
+        let outgoing = self.outgoing.borrow();
+        let index = outgoing
+            .index
+            .0
+            .as_ref()
+            .and_then(|index| index.as_any().downcast_ref::<KeyedChildIndex<K, E>>())
+            .ok_or(NotIndexed)?;
+        let child = index.get(key).find_map(|k| outgoing.get(k)?.upgrade());
+        Ok(child)
+    }
+
+    /// The targets of all outgoing edges, as weak references.
+    fn outgoing_targets(&self) -> Vec<RelWeak<N, E>> {
+        self.outgoing
+            .borrow()
+            .iter()
+            .map(|e| e.target.clone())
+            .collect()
     }
 }
 
-fn register_outgoing_edges<N, E>(incoming: &[InnerEdgeData<N, E>]) {
-    for (i, edge) in incoming.iter().enumerate() {
-        edge.source().outgoing.borrow_mut().push(edge.downgrade(i));
+/// Add the incoming edges of a new node to the outgoing edges of its sources.
+fn register_outgoing_edges<N, E>(node: &InnerData<N, E>) {
+    for (i, edge) in node.incoming.iter().enumerate() {
+        if let Some(source) = edge.upgrade_source() {
+            let key = (node.creation_order(), i);
+            let weak_edge = edge.downgrade(i);
+            source
+                .outgoing
+                .borrow_mut()
+                .push(key, weak_edge, edge.value());
+        }
+    }
+}
+
+/// Remove the incoming edges of a node from the outgoing edges of its sources.
+///
+/// Edges of sources whose outgoing edges are currently borrowed are skipped;
+/// they will be reclaimed at the next compaction.
+fn unregister_outgoing_edges<N, E>(node: &InnerData<N, E>) {
+    for (i, edge) in node.incoming.iter().enumerate() {
+        let Some(source) = edge.upgrade_source() else {
+            continue;
+        };
+        if let Ok(mut outgoing) = source.outgoing.try_borrow_mut() {
+            outgoing.remove((node.creation_order(), i), edge.value());
+        };
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::HistoryGraph;
 
     #[test]
     fn test_cleanup_dead_references() {
@@ -385,7 +955,7 @@ mod tests {
 
         let id = {
             let node = RelRc::new("test");
-            let id = node.try_register_in(&registry).unwrap();
+            let id = node.register_in(&registry);
 
             assert_eq!(registry.borrow().len(), 1);
             id
@@ -398,4 +968,211 @@ mod tests {
         assert!(!registry.contains_id(id));
         assert_eq!(registry.len(), 0);
     }
+
+    #[test]
+    fn test_all_descendants() {
+        // root -> (left, right) -> bottom, and a dropped child of left
+        let root = RelRc::new("root");
+        let left = RelRc::with_parents("left", [(root.clone(), ())]);
+        let right = RelRc::with_parents("right", [(root.clone(), ())]);
+        let bottom = RelRc::with_parents("bottom", [(right.clone(), ()), (left.clone(), ())]);
+        drop(RelRc::with_parents("dropped", [(left.clone(), ())]));
+
+        let descendants = root.all_descendants().map(|n| *n.value()).collect_vec();
+        assert_eq!(descendants, ["left", "right", "bottom"]);
+        assert_eq!(bottom.all_descendants().count(), 0);
+
+        let topological = root
+            .all_descendants_topological()
+            .map(|n| *n.value())
+            .collect_vec();
+        assert_eq!(topological.len(), 3);
+        assert_eq!(topological.last(), Some(&"bottom"));
+        assert_eq!(
+            left.all_descendants_topological()
+                .map(|n| *n.value())
+                .collect_vec(),
+            ["bottom"]
+        );
+
+        // Every descendant comes after all of its parents among the descendants,
+        // including `tail`, which is reached from `left` before `bottom`
+        let _tail = RelRc::with_parents("tail", [(left.clone(), ()), (bottom.clone(), ())]);
+        for start in [&root, &left, &right] {
+            let topological = start.all_descendants_topological().collect_vec();
+            let position = |n: &RelRc<_, _>| topological.iter().position(|m| m.ptr_eq(n));
+            assert_eq!(topological.len(), start.all_descendants().count());
+            for (i, node) in topological.iter().enumerate() {
+                for parent in node.all_parents() {
+                    assert!(position(parent).map_or(true, |j| j < i));
+                }
+            }
+        }
+        assert_eq!(
+            left.all_descendants_topological()
+                .map(|n| *n.value())
+                .collect_vec(),
+            ["bottom", "tail"]
+        );
+    }
+
+    #[test]
+    fn test_outgoing_order_and_compaction() {
+        let root = RelRc::<_, ()>::new(0);
+        let (kept, dropped): (Vec<_>, Vec<_>) = (1..=10)
+            .map(|i| RelRc::with_parents(i, [(root.clone(), ())]))
+            .partition(|child| child.value() % 3 == 0);
+        drop(dropped);
+
+        let children = |n: &RelRc<usize, ()>| n.all_children().map(|c| *c.value()).collect_vec();
+        assert_eq!(children(&root), [3, 6, 9]);
+        assert_eq!(root.n_outgoing(), 3);
+        // The removed edges of the first 6 dropped children have been reclaimed
+        assert_eq!(root.outgoing.borrow().edges.len(), 4);
+
+        let last = RelRc::with_parents(11, [(root.clone(), ())]);
+        assert_eq!(children(&root), [3, 6, 9, 11]);
+        drop((kept, last));
+        assert_eq!(root.n_outgoing(), 0);
+    }
+
+    #[test]
+    fn test_multiple_registries() {
+        let root = RelRc::new("root");
+        let left = RelRc::with_parents("left", [(root.clone(), ())]);
+        let right = RelRc::with_parents("right", [(root.clone(), ())]);
+
+        // Two independent graphs over overlapping histories
+        let mut left_graph = HistoryGraph::default();
+        let mut right_graph = HistoryGraph::default();
+        let left_id = left_graph.insert_ancestors(left.clone());
+        let right_id = right_graph.insert_ancestors(right);
+        assert_eq!(root.n_registries(), 2);
+        assert_eq!(left.n_registries(), 1);
+        let root_ids =
+            [&left_graph, &right_graph].map(|g| g.registry().borrow().get_id(&root).unwrap());
+
+        // Registering twice in the same registry keeps the same ID
+        assert_eq!(root.register_in(left_graph.registry()), root_ids[0]);
+        assert_eq!(root.n_registries(), 2);
+
+        // Dropping the node frees its ID in every registry
+        let left_registry = left_graph.registry().clone();
+        let right_registry = right_graph.registry().clone();
+        drop((left_graph, right_graph, left));
+        assert!(!left_registry.borrow().contains_id(left_id));
+        assert!(!right_registry.borrow().contains_id(right_id));
+        assert!(left_registry.borrow().contains_id(root_ids[0]));
+        drop(root);
+        assert!(!left_registry.borrow().contains_id(root_ids[0]));
+        assert!(!right_registry.borrow().contains_id(root_ids[1]));
+        assert_eq!(left_registry.borrow().len(), 0);
+        assert_eq!(right_registry.borrow().len(), 0);
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let registry = Rc::new(RefCell::new(Registry::new()));
+        let parent = RelRc::new("parent".to_string());
+        let child = RelRc::with_parents("child".to_string(), [(parent.clone(), 42)]);
+        let child_id = child.register_in(&registry);
+
+        // The parent is held by the child
+        let parent = parent.try_unwrap().unwrap_err();
+        assert!(parent.clone().into_inner().is_none());
+
+        let (value, parents) = child.try_unwrap().unwrap();
+        assert_eq!(value, "child");
+        assert_eq!(parents.len(), 1);
+        assert!(parents[0].0.as_strong().unwrap().ptr_eq(&parent));
+        assert_eq!(parents[0].1, 42);
+        assert!(!registry.borrow().contains_id(child_id));
+        assert!(parent.all_outgoing_weak().is_empty());
+
+        drop(parents);
+        assert_eq!(parent.into_inner().unwrap().0, "parent");
+    }
+
+    #[test]
+    fn test_try_unwrap_weak_child() {
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_sources("child", [(EdgeSource::Weak(parent.downgrade()), 1)]);
+        assert_eq!(parent.n_outgoing(), 1);
+
+        // The child does not hold the parent
+        let (value, parents) = parent.try_unwrap().unwrap();
+        assert_eq!(value, "parent");
+        assert!(parents.is_empty());
+        assert!(child.incoming(0).unwrap().is_dangling());
+        assert_eq!(child.all_dangling().count(), 1);
+    }
+
+    #[test]
+    fn test_weak_edges() {
+        let strong = RelRc::new("strong");
+        let weak = RelRc::new("weak");
+        let child = RelRc::with_sources(
+            "child",
+            [
+                (EdgeSource::Strong(strong.clone()), 0),
+                (EdgeSource::Weak(weak.downgrade()), 1),
+            ],
+        );
+        assert!(child.incoming(1).unwrap().is_weak());
+        assert_eq!(child.generation(), 1);
+        assert!(child.parent(1).is_none());
+        assert_eq!(weak.all_children().len(), 1);
+        assert_eq!(child.all_parents().count(), 1);
+        assert_eq!(child.all_ancestors().count(), 2);
+
+        let mut graph = HistoryGraph::from_nodes([strong.clone(), weak.clone(), child.clone()]);
+        let child_id = graph.insert_ancestors(child.clone());
+        assert_eq!(graph.parents(child_id).count(), 2);
+        assert_eq!(graph.dangling_edges(child_id).count(), 0);
+
+        // The weak parent is not kept alive by its child
+        drop(graph);
+        drop(weak);
+        assert!(child.incoming(1).unwrap().is_dangling());
+        assert_eq!(child.all_dangling().count(), 1);
+        assert!(child.incoming(1).unwrap().upgrade_source().is_none());
+
+        let graph = HistoryGraph::from_nodes([child.clone()]);
+        let child_id = graph.all_node_ids().exactly_one().ok().unwrap();
+        let dangling = graph.dangling_edges(child_id).exactly_one().ok().unwrap();
+        assert_eq!(dangling.index, 1);
+        assert!(!graph.contains_edge(dangling));
+
+        // Dropping the child only releases the strong parent
+        drop(graph);
+        drop(child);
+        assert_eq!(strong.n_outgoing(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "slow in debug builds")]
+    fn test_deep_chain() {
+        const DEPTH: usize = 1_000_000;
+
+        let root = RelRc::new(0);
+        let mut tip = root.clone();
+        for i in 1..DEPTH {
+            tip = RelRc::with_parents(i, [(tip, ())]);
+        }
+
+        // Insert all ancestors into a history graph
+        let mut graph = HistoryGraph::default();
+        graph.insert_ancestors(tip.clone());
+        assert_eq!(graph.all_node_ids().count(), DEPTH);
+        drop(graph);
+
+        // Serialization round trip
+        let deser = RelRc::from_serialized(tip.to_serialized(Registry::new()).unwrap());
+        assert_eq!(deser.value(), &(DEPTH - 1));
+
+        // Dropping the tips frees the whole chain
+        drop(deser);
+        drop(tip);
+        assert_eq!(root.n_outgoing(), 0);
+    }
 }
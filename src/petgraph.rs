@@ -13,16 +13,17 @@ use petgraph::{
     Direction,
 };
 
-use crate::{EdgeId, HistoryGraph, NodeId};
+use crate::registry::KeyStrategy;
+use crate::{EdgeId, HistoryGraph};
 
-impl<'a, N, E> GraphBase for &'a HistoryGraph<N, E> {
-    type EdgeId = EdgeId;
-    type NodeId = NodeId;
+impl<N, E, S: KeyStrategy> GraphBase for &HistoryGraph<N, E, S> {
+    type EdgeId = EdgeId<S::Key>;
+    type NodeId = S::Key;
 }
 
-impl<'a, N, E> GraphRef for &'a HistoryGraph<N, E> {}
+impl<N, E, S: KeyStrategy> GraphRef for &HistoryGraph<N, E, S> {}
 
-impl<'a, N, E> IntoNeighbors for &'a HistoryGraph<N, E> {
+impl<'a, N, E, S: KeyStrategy> IntoNeighbors for &'a HistoryGraph<N, E, S> {
     type Neighbors = Box<dyn Iterator<Item = Self::NodeId> + 'a>;
 
     fn neighbors(self, n: Self::NodeId) -> Self::Neighbors {
@@ -30,7 +31,7 @@ impl<'a, N, E> IntoNeighbors for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> IntoNeighborsDirected for &'a HistoryGraph<N, E> {
+impl<'a, N, E, S: KeyStrategy> IntoNeighborsDirected for &'a HistoryGraph<N, E, S> {
     type NeighborsDirected = Box<dyn Iterator<Item = Self::NodeId> + 'a>;
 
     fn neighbors_directed(self, node_id: Self::NodeId, d: Direction) -> Self::NeighborsDirected {
@@ -44,13 +45,13 @@ impl<'a, N, E> IntoNeighborsDirected for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> Data for &'a HistoryGraph<N, E> {
+impl<N, E, S: KeyStrategy> Data for &HistoryGraph<N, E, S> {
     type NodeWeight = N;
     type EdgeWeight = E;
 }
 
-impl<'a, N, E> IntoEdgeReferences for &'a HistoryGraph<N, E> {
-    type EdgeRef = EdgeRef<'a, N, E>;
+impl<'a, N, E, S: KeyStrategy> IntoEdgeReferences for &'a HistoryGraph<N, E, S> {
+    type EdgeRef = EdgeRef<'a, N, E, S>;
 
     type EdgeReferences = Box<dyn Iterator<Item = Self::EdgeRef> + 'a>;
 
@@ -62,7 +63,7 @@ impl<'a, N, E> IntoEdgeReferences for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> IntoNodeIdentifiers for &'a HistoryGraph<N, E> {
+impl<'a, N, E, S: KeyStrategy> IntoNodeIdentifiers for &'a HistoryGraph<N, E, S> {
     type NodeIdentifiers = Box<dyn Iterator<Item = Self::NodeId> + 'a>;
 
     fn node_identifiers(self) -> Self::NodeIdentifiers {
@@ -70,7 +71,7 @@ impl<'a, N, E> IntoNodeIdentifiers for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> IntoEdges for &'a HistoryGraph<N, E> {
+impl<'a, N, E, S: KeyStrategy> IntoEdges for &'a HistoryGraph<N, E, S> {
     type Edges = Box<dyn Iterator<Item = Self::EdgeRef> + 'a>;
 
     fn edges(self, node_id: Self::NodeId) -> Self::Edges {
@@ -81,7 +82,7 @@ impl<'a, N, E> IntoEdges for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> IntoEdgesDirected for &'a HistoryGraph<N, E> {
+impl<'a, N, E, S: KeyStrategy> IntoEdgesDirected for &'a HistoryGraph<N, E, S> {
     type EdgesDirected = Box<dyn Iterator<Item = Self::EdgeRef> + 'a>;
 
     fn edges_directed(self, node_id: Self::NodeId, d: Direction) -> Self::EdgesDirected {
@@ -98,8 +99,8 @@ impl<'a, N, E> IntoEdgesDirected for &'a HistoryGraph<N, E> {
     }
 }
 
-impl<'a, N, E> Visitable for &'a HistoryGraph<N, E> {
-    type Map = HashSet<NodeId>;
+impl<N, E, S: KeyStrategy> Visitable for &HistoryGraph<N, E, S> {
+    type Map = HashSet<S::Key>;
 
     #[doc = r" Create a new visitor map"]
     fn visit_map(&self) -> Self::Map {
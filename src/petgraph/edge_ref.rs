@@ -2,7 +2,8 @@ use derive_where::derive_where;
 use petgraph::visit;
 
 use crate::edge::InnerEdgeData;
-use crate::{HistoryGraph, NodeId};
+use crate::registry::{KeyStrategy, SlotKeys};
+use crate::HistoryGraph;
 
 use crate::history::EdgeId;
 
@@ -12,13 +13,13 @@ use crate::history::EdgeId;
 /// for the lifetime `'a`.
 #[derive(Debug)]
 #[derive_where(Clone, Copy)]
-pub struct EdgeRef<'a, N, E> {
-    id: EdgeId,
-    history: &'a HistoryGraph<N, E>,
+pub struct EdgeRef<'a, N, E, S: KeyStrategy = SlotKeys> {
+    id: EdgeId<S::Key>,
+    history: &'a HistoryGraph<N, E, S>,
 }
 
-impl<'a, N, E> EdgeRef<'a, N, E> {
-    pub(super) fn new(id: EdgeId, history: &'a HistoryGraph<N, E>) -> Self {
+impl<'a, N, E, S: KeyStrategy> EdgeRef<'a, N, E, S> {
+    pub(super) fn new(id: EdgeId<S::Key>, history: &'a HistoryGraph<N, E, S>) -> Self {
         Self { id, history }
     }
 
@@ -30,10 +31,10 @@ impl<'a, N, E> EdgeRef<'a, N, E> {
     }
 }
 
-impl<'a, N, E> visit::EdgeRef for EdgeRef<'a, N, E> {
-    type NodeId = NodeId;
+impl<'a, N, E, S: KeyStrategy> visit::EdgeRef for EdgeRef<'a, N, E, S> {
+    type NodeId = S::Key;
 
-    type EdgeId = EdgeId;
+    type EdgeId = EdgeId<S::Key>;
 
     type Weight = E;
 
@@ -0,0 +1,95 @@
+//! The kinds of reference-counted pointers to nodes.
+//!
+//! Edges and ancestor traversals are shared between single-threaded
+//! [`RelRc`] nodes and thread-safe [`RelArc`] nodes: they are generic over the
+//! [`PointerKind`] of the nodes, which defaults to [`Local`].
+
+use crate::edge::{EdgeSource, InnerEdgeData};
+use crate::{RelArc, RelArcWeak, RelRc, RelWeak};
+
+/// A kind of reference-counted pointers to nodes: [`Local`] or [`Shared`].
+pub trait PointerKind: Sized + 'static {
+    /// A strong reference to a node.
+    type Node<N, E>: Clone;
+    /// A weak reference to a node.
+    type Weak<N, E>: Clone;
+    /// The source of an edge, as stored by the edge.
+    type Source<N, E>: Clone;
+
+    /// Upgrade a weak reference to a node, if it is still alive.
+    fn upgrade<N, E>(weak: &Self::Weak<N, E>) -> Option<Self::Node<N, E>>;
+
+    /// The incoming edges of a node.
+    fn incoming<N, E>(node: &Self::Node<N, E>) -> &[InnerEdgeData<N, E, Self>];
+
+    /// The source of an edge, if the edge keeps it alive.
+    fn strong_source<N, E>(source: &Self::Source<N, E>) -> Option<&Self::Node<N, E>>;
+
+    /// The address of a node, which identifies it.
+    fn node_addr<N, E>(node: &Self::Node<N, E>) -> *const ();
+
+    /// The address of a weakly referenced node, which identifies it.
+    fn weak_addr<N, E>(weak: &Self::Weak<N, E>) -> *const ();
+}
+
+/// Single-threaded [`RelRc`] pointers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Local;
+
+impl PointerKind for Local {
+    type Node<N, E> = RelRc<N, E>;
+    type Weak<N, E> = RelWeak<N, E>;
+    type Source<N, E> = EdgeSource<N, E>;
+
+    fn upgrade<N, E>(weak: &RelWeak<N, E>) -> Option<RelRc<N, E>> {
+        weak.upgrade()
+    }
+
+    fn incoming<N, E>(node: &RelRc<N, E>) -> &[InnerEdgeData<N, E>] {
+        node.all_incoming()
+    }
+
+    fn strong_source<N, E>(source: &EdgeSource<N, E>) -> Option<&RelRc<N, E>> {
+        source.as_strong()
+    }
+
+    fn node_addr<N, E>(node: &RelRc<N, E>) -> *const () {
+        node.as_ptr() as *const ()
+    }
+
+    fn weak_addr<N, E>(weak: &RelWeak<N, E>) -> *const () {
+        weak.as_ptr() as *const ()
+    }
+}
+
+/// Thread-safe [`RelArc`] pointers.
+///
+/// Edges between [`RelArc`] nodes always keep their source alive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Shared;
+
+impl PointerKind for Shared {
+    type Node<N, E> = RelArc<N, E>;
+    type Weak<N, E> = RelArcWeak<N, E>;
+    type Source<N, E> = RelArc<N, E>;
+
+    fn upgrade<N, E>(weak: &RelArcWeak<N, E>) -> Option<RelArc<N, E>> {
+        weak.upgrade()
+    }
+
+    fn incoming<N, E>(node: &RelArc<N, E>) -> &[InnerEdgeData<N, E, Shared>] {
+        node.all_incoming()
+    }
+
+    fn strong_source<N, E>(source: &RelArc<N, E>) -> Option<&RelArc<N, E>> {
+        Some(source)
+    }
+
+    fn node_addr<N, E>(node: &RelArc<N, E>) -> *const () {
+        node.as_ptr() as *const ()
+    }
+
+    fn weak_addr<N, E>(weak: &RelArcWeak<N, E>) -> *const () {
+        weak.as_ptr() as *const ()
+    }
+}
@@ -1,84 +1,458 @@
 //! Node registry for assigning unique IDs to RelRc nodes.
 
+mod keys;
+
+pub use keys::{
+    ContentKeys, KeyMap, KeyStrategy, NodeId, SequentialKeys, SequentialMap, SlotKeys, SuppliedKeys,
+};
+
 use derive_where::derive_where;
-use slotmap_fork_lmondada::{new_key_type, SlotMap};
+use itertools::Itertools;
+use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::rc::{Rc, Weak};
+use thiserror::Error;
 
 use crate::RelWeak;
 use crate::{node::InnerData, RelRc};
 
-new_key_type! {
-    /// A unique identifier for a node in the registry.
-    pub struct NodeId;
-}
+/// Error type for nodes that the key strategy of a registry could not assign
+/// a key to, see [`KeyStrategy::insert_new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("The key strategy could not assign a key to the node")]
+pub struct UnassignedKey;
 
 /// Shared registry for assigning unique IDs to RelRc nodes.
 ///
 /// Multiple graphs can share the same registry to maintain consistent
 /// node identification across graphs.
-#[derive(Debug)]
-#[derive_where(Clone, Default)]
-pub struct Registry<N, E> {
-    /// Map from NodeId to weak references to nodes
-    nodes: SlotMap<NodeId, RelWeak<N, E>>,
-    /// Inverse map from raw pointer to NodeId for fast lookups
-    ptr_to_id: HashMap<*const InnerData<N, E>, NodeId>,
+///
+/// The keys assigned to the nodes are determined by the [`KeyStrategy`] `S`.
+/// By default, nodes are assigned [`NodeId`]s.
+///
+/// Cloning a registry copies its nodes and keys. The clone carries its own
+/// pins, so that pinned nodes stay alive until they are unpinned from every
+/// clone. Nodes do not track the clones of their registries: a clone does not
+/// free the IDs of dropped nodes until [`Registry::free_node_ids`] is called.
+/// Subscriptions are not cloned.
+pub struct Registry<N, E, S: KeyStrategy = SlotKeys> {
+    /// Map from keys to weak references to nodes, assigning keys to new
+    /// nodes
+    nodes: S::Map<RelWeak<N, E>>,
+    /// Inverse map from raw pointer to key for fast lookups
+    ptr_to_id: HashMap<*const InnerData<N, E>, S::Key>,
+    /// Callbacks to call when a registered node is dropped, and dropped nodes
+    /// yet to be removed.
+    drop_tracker: RegistryDropTracker<N, E>,
+    /// Strong references to pinned nodes, with their number of pins.
+    pins: BTreeMap<S::Key, (RelRc<N, E>, usize)>,
+    /// Subscribers to registry events, and the events yet to be delivered.
+    subscribers: RegistrySubscribers<S::Key>,
+}
+
+/// An event emitted by a [`Registry`], see [`Registry::subscribe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistryEvent<K = NodeId> {
+    /// A node was registered with the given ID.
+    Registered(K),
+    /// The given ID was freed, as its node was removed from the registry or
+    /// dropped.
+    Freed(K),
+}
+
+/// A registry event callback, see [`Registry::subscribe`].
+type RegistrySubscriber<K> = dyn Fn(RegistryEvent<K>);
+
+/// A subscription to the events of a [`Registry`].
+///
+/// The subscription is cancelled when dropped.
+#[must_use = "the subscription is cancelled when dropped"]
+pub struct Subscription {
+    _subscriber: Rc<dyn Any>,
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").finish_non_exhaustive()
+    }
+}
+
+/// The subscribers of a registry and the events not yet delivered to them.
+///
+/// Subscriptions belong to a registry and are not cloned along with it.
+#[derive_where(Default)]
+struct RegistrySubscribers<K> {
+    subscribers: Vec<Weak<RegistrySubscriber<K>>>,
+    pending: VecDeque<RegistryEvent<K>>,
+}
+
+impl<K> RegistrySubscribers<K> {
+    /// Queue an event for delivery, if anyone is listening.
+    fn emit(&mut self, event: RegistryEvent<K>) {
+        if self.subscribers.iter().any(|s| s.strong_count() > 0) {
+            self.pending.push_back(event);
+        }
+    }
+}
+
+impl<K> Clone for RegistrySubscribers<K> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<K: std::fmt::Debug> std::fmt::Debug for RegistrySubscribers<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistrySubscribers")
+            .field("subscribers", &self.subscribers.len())
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+/// A registry drop callback, see [`Registry::on_drop`].
+pub(crate) type RegistryDropObserver<N, E> = Rc<dyn Fn(&N, &[&E])>;
+
+/// The drop callbacks of a registry, and the nodes that were dropped but not
+/// removed from the registry yet.
+///
+/// Nodes access it outside of the `RefCell` of their registries, so that they
+/// can be dropped while a registry is borrowed.
+#[derive_where(Default)]
+pub(crate) struct DropTracker<N, E> {
+    observers: RefCell<Vec<RegistryDropObserver<N, E>>>,
+    dropped: RefCell<Vec<*const InnerData<N, E>>>,
+}
+
+impl<N, E> DropTracker<N, E> {
+    /// Queue the node at the given address for removal from the registry,
+    /// and return the callbacks to call for its drop.
+    pub(crate) fn node_dropped(
+        &self,
+        ptr: *const InnerData<N, E>,
+    ) -> Vec<RegistryDropObserver<N, E>> {
+        self.dropped.borrow_mut().push(ptr);
+        self.observers.borrow().clone()
+    }
+}
+
+/// The drop tracker of a registry.
+///
+/// A clone of a registry gets its own copy of the drop callbacks.
+#[derive_where(Default)]
+struct RegistryDropTracker<N, E>(Rc<DropTracker<N, E>>);
+
+impl<N, E> Clone for RegistryDropTracker<N, E> {
+    fn clone(&self) -> Self {
+        let tracker = DropTracker {
+            observers: self.0.observers.clone(),
+            dropped: Default::default(),
+        };
+        Self(Rc::new(tracker))
+    }
+}
+
+impl<N, E> std::fmt::Debug for RegistryDropTracker<N, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryDropTracker")
+            .field("observers", &self.0.observers.borrow().len())
+            .field("dropped", &self.0.dropped.borrow().len())
+            .finish()
+    }
+}
+
+/// The operations on a registry that its nodes need, independently of the
+/// key strategy of the registry.
+pub(crate) trait NodeRegistry<N, E> {
+    /// Remove the nodes queued by the drop tracker from the registry.
+    fn remove_dropped(&mut self);
+
+    /// Take the pending events of the registry, and return a function that
+    /// delivers them to the subscribers.
+    fn take_events(&mut self) -> Option<Box<dyn FnOnce()>>;
+}
+
+impl<N, E, S: KeyStrategy> NodeRegistry<N, E> for Registry<N, E, S> {
+    fn remove_dropped(&mut self) {
+        let dropped = std::mem::take(&mut *self.drop_tracker.0.dropped.borrow_mut());
+        for ptr in dropped {
+            let Some(&id) = self.ptr_to_id.get(&ptr) else {
+                continue;
+            };
+            // The node may have been removed and its address reused since
+            if self.nodes.get(id).is_some_and(|n| n.strong_count() == 0) {
+                self.remove_entry(id);
+            }
+        }
+    }
+
+    fn take_events(&mut self) -> Option<Box<dyn FnOnce()>> {
+        let subscribers = &mut self.subscribers;
+        if subscribers.pending.is_empty() {
+            return None;
+        }
+        subscribers.subscribers.retain(|s| s.strong_count() > 0);
+        let live = subscribers
+            .subscribers
+            .iter()
+            .filter_map(|s| s.upgrade())
+            .collect_vec();
+        let events = std::mem::take(&mut subscribers.pending);
+        Some(Box::new(move || {
+            for event in events {
+                for subscriber in &live {
+                    subscriber(event);
+                }
+            }
+        }))
+    }
+}
+
+/// Remove the dropped nodes from a registry.
+///
+/// Does nothing if the registry is currently borrowed: the nodes are then
+/// removed by the next call, or by [`dispatch_events`].
+pub(crate) fn remove_dropped<N, E, R: NodeRegistry<N, E> + ?Sized>(registry: &RefCell<R>) {
+    if let Ok(mut registry) = registry.try_borrow_mut() {
+        registry.remove_dropped();
+    }
+}
+
+/// Remove the dropped nodes from a registry and deliver its pending events
+/// to its subscribers.
+///
+/// Does nothing if the registry is currently borrowed.
+pub(crate) fn dispatch_events<N, E, R: NodeRegistry<N, E> + ?Sized>(registry: &RefCell<R>) {
+    loop {
+        let Some(deliver) = registry.try_borrow_mut().ok().and_then(|mut registry| {
+            registry.remove_dropped();
+            registry.take_events()
+        }) else {
+            return;
+        };
+        // Call the subscribers once the borrow has been released
+        deliver();
+    }
+}
+
+/// A weak reference to a registry that tracks a node, of any key strategy.
+#[derive_where(Clone)]
+pub(crate) struct RegistryLink<N, E> {
+    registry: Weak<RefCell<dyn NodeRegistry<N, E>>>,
+    any: Weak<dyn Any>,
+    drop_tracker: Weak<DropTracker<N, E>>,
+}
+
+impl<N: 'static, E: 'static> RegistryLink<N, E> {
+    pub(crate) fn new<S: KeyStrategy>(registry: &Rc<RefCell<Registry<N, E, S>>>) -> Self {
+        let registry_dyn: Rc<RefCell<dyn NodeRegistry<N, E>>> = registry.clone();
+        let any: Rc<dyn Any> = registry.clone();
+        let drop_tracker = Rc::downgrade(&registry.borrow().drop_tracker.0);
+        Self {
+            registry: Rc::downgrade(&registry_dyn),
+            any: Rc::downgrade(&any),
+            drop_tracker,
+        }
+    }
+
+    /// The registry, if it is alive and has the key strategy `S`.
+    pub(crate) fn downcast<S: KeyStrategy>(&self) -> Option<Rc<RefCell<Registry<N, E, S>>>> {
+        self.any.upgrade()?.downcast().ok()
+    }
+}
+
+impl<N, E> RegistryLink<N, E> {
+    pub(crate) fn upgrade(&self) -> Option<Rc<RefCell<dyn NodeRegistry<N, E>>>> {
+        self.registry.upgrade()
+    }
+
+    /// The drop tracker of the registry, if it is alive.
+    ///
+    /// Unlike the registry, the tracker is never borrowed for long.
+    pub(crate) fn drop_tracker(&self) -> Option<Rc<DropTracker<N, E>>> {
+        self.drop_tracker.upgrade()
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.registry.strong_count() > 0
+    }
+
+    /// Whether the link points to `registry`.
+    pub(crate) fn points_to<S: KeyStrategy>(
+        &self,
+        registry: &Rc<RefCell<Registry<N, E, S>>>,
+    ) -> bool {
+        self.any.as_ptr() as *const () == Rc::as_ptr(registry) as *const ()
+    }
+}
+
+impl<N, E> std::fmt::Debug for RegistryLink<N, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryLink")
+            .field("alive", &self.is_alive())
+            .finish()
+    }
 }
 
 impl<N, E> Registry<N, E> {
-    /// Create a new empty node registry.
+    /// Create a new empty node registry, assigning [`NodeId`]s.
     pub fn new() -> Self {
+        Self::with_keys(SlotKeys)
+    }
+}
+
+impl<N, E, S: KeyStrategy> Default for Registry<N, E, S> {
+    fn default() -> Self {
+        Self::with_keys(S::default())
+    }
+}
+
+impl<N, E, S: KeyStrategy> Clone for Registry<N, E, S> {
+    fn clone(&self) -> Self {
+        Self {
+            nodes: S::map_values(&self.nodes, |_, node| node.clone()),
+            ptr_to_id: self.ptr_to_id.clone(),
+            drop_tracker: self.drop_tracker.clone(),
+            pins: self.pins.clone(),
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl<N: std::fmt::Debug, E: std::fmt::Debug, S: KeyStrategy> std::fmt::Debug for Registry<N, E, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("nodes", &self.nodes.iter().collect::<BTreeMap<_, _>>())
+            .field("ptr_to_id", &self.ptr_to_id)
+            .field("drop_tracker", &self.drop_tracker)
+            .field("pins", &self.pins)
+            .field("subscribers", &self.subscribers)
+            .finish()
+    }
+}
+
+impl<N, E, S: KeyStrategy> Registry<N, E, S> {
+    /// Create a new empty node registry, assigning keys with `keys`.
+    pub fn with_keys(keys: S) -> Self {
         Self {
-            nodes: SlotMap::with_key(),
+            nodes: keys.new_map(),
             ptr_to_id: HashMap::new(),
+            drop_tracker: Default::default(),
+            pins: BTreeMap::new(),
+            subscribers: Default::default(),
         }
     }
 
-    /// Create a new registry from a slotmap of nodes.
+    /// Create a new registry from its nodes.
     ///
     /// Only used internally, to recreate a [`Registry`] from its serialized
     /// format.
-    pub(crate) fn from_slotmap(nodes: SlotMap<NodeId, RelWeak<N, E>>) -> Self {
+    pub(crate) fn from_nodes(nodes: S::Map<RelWeak<N, E>>) -> Self {
         let ptr_to_id = nodes
             .iter()
             .map(|(id, weak_ref)| (weak_ref.as_ptr(), id))
             .collect();
-        Self { nodes, ptr_to_id }
+        Self {
+            nodes,
+            ptr_to_id,
+            ..Self::default()
+        }
     }
 
     /// Add a RelRc node to the registry and return its unique ID.
     ///
     /// If the node is already in the registry, return the existing ID.
     ///
-    /// Prefer registering the node using [RelRc::try_register_in] or
+    /// Prefer registering the node using [RelRc::register_in] or
     /// [crate::HistoryGraph::insert_node], as these will also free the
     /// node ID when the node goes out of scope.
-    pub fn add_node(&mut self, node: &RelRc<N, E>) -> NodeId {
+    ///
+    /// Panics if the key strategy cannot assign a key to the node, see
+    /// [`Registry::try_add_node`].
+    pub fn add_node(&mut self, node: &RelRc<N, E>) -> S::Key {
+        self.try_add_node(node)
+            .expect("the key strategy could not assign a key to the node")
+    }
+
+    /// Add a RelRc node to the registry and return its unique ID.
+    ///
+    /// If the node is already in the registry, return the existing ID.
+    /// Returns `None` if the key strategy cannot assign a key to the node, or
+    /// if the key is in use by another live node.
+    pub fn try_add_node(&mut self, node: &RelRc<N, E>) -> Option<S::Key> {
         // Fast lookup using inverse map
         if let Some(existing_id) = self.get_id(node) {
-            return existing_id; // node is already registered
+            return Some(existing_id); // node is already registered
         }
+        let (id, previous) = S::insert_new(&mut self.nodes, node, node.downgrade())?;
+        self.inserted(id, node, previous)
+    }
+
+    /// Add a RelRc node to the registry with the given key.
+    ///
+    /// Returns `None` if the key is in use by another live node, if the node
+    /// is already registered with a different key, or if the key strategy
+    /// does not accept the key for the node.
+    pub fn try_add_node_with_key(&mut self, node: &RelRc<N, E>, id: S::Key) -> Option<S::Key> {
+        if let Some(existing_id) = self.get_id(node) {
+            return (existing_id == id).then_some(id);
+        }
+        if self.contains_id(id) {
+            return None;
+        }
+        let previous = S::insert_with_key(&mut self.nodes, node, id, node.downgrade()).ok()?;
+        self.inserted(id, node, previous)
+    }
 
-        // Register new node
-        let weak_ref = node.downgrade();
-        let id = self.nodes.insert(weak_ref);
+    /// Record that `node` was inserted with the key `id`, in place of
+    /// `previous`.
+    ///
+    /// If `previous` is a live node, the key is in use: `previous` is
+    /// restored and `None` is returned.
+    fn inserted(
+        &mut self,
+        id: S::Key,
+        node: &RelRc<N, E>,
+        previous: Option<RelWeak<N, E>>,
+    ) -> Option<S::Key> {
+        if let Some(previous) = previous {
+            if previous.strong_count() > 0 {
+                let previous_node = previous.upgrade().expect("live node");
+                let restored = S::insert_with_key(&mut self.nodes, &previous_node, id, previous);
+                debug_assert!(restored.is_ok());
+                return None;
+            }
+            // Free the key of a dropped node that was not removed yet
+            self.ptr_to_id.remove(&previous.as_ptr());
+            self.subscribers.emit(RegistryEvent::Freed(id));
+        }
         self.ptr_to_id.insert(node.as_ptr(), id);
+        self.subscribers.emit(RegistryEvent::Registered(id));
+        Some(id)
+    }
 
-        id
+    /// Remove the node with the given key from both maps, and free the key.
+    fn remove_entry(&mut self, id: S::Key) {
+        if let Some(weak_ref) = self.nodes.remove(id) {
+            self.ptr_to_id.remove(&weak_ref.as_ptr());
+            self.subscribers.emit(RegistryEvent::Freed(id));
+        }
     }
 
-    /// Get the NodeId for a RelRc node if it's registered.
+    /// Get the key of a RelRc node if it's registered.
     ///
     /// This operation is O(1) thanks to the inverse map.
-    pub fn get_id(&self, node: &RelRc<N, E>) -> Option<NodeId> {
+    pub fn get_id(&self, node: &RelRc<N, E>) -> Option<S::Key> {
         self.ptr_to_id.get(&node.as_ptr()).copied()
     }
 
-    /// Get the NodeId for a RelRc node if it's registered, otherwise add it
+    /// Get the key of a RelRc node if it's registered, otherwise add it
     /// to the registry.
-    pub fn get_id_or_insert(&mut self, node: &RelRc<N, E>) -> NodeId {
+    ///
+    /// Panics if the key strategy cannot assign a key to the node, see
+    /// [`Registry::try_add_node`].
+    pub fn get_id_or_insert(&mut self, node: &RelRc<N, E>) -> S::Key {
         if let Some(id) = self.get_id(node) {
             id
         } else {
@@ -94,14 +468,12 @@ impl<N, E> Registry<N, E> {
     /// Get the [`RelRc`] node associated with the given ID.
     ///
     /// If the weak reference cannot be upgraded, none is returned.
-    pub fn get(&self, id: NodeId) -> Option<RelRc<N, E>> {
-        let weak_ref = self.nodes.get(id).cloned()?;
-
-        weak_ref.upgrade()
+    pub fn get(&self, id: S::Key) -> Option<RelRc<N, E>> {
+        self.nodes.get(id)?.upgrade()
     }
 
     /// Check if a node ID is registered.
-    pub fn contains_id(&self, id: NodeId) -> bool {
+    pub fn contains_id(&self, id: S::Key) -> bool {
         self.get(id).is_some()
     }
 
@@ -110,30 +482,20 @@ impl<N, E> Registry<N, E> {
     /// This is automatically done during `get()` calls, but can be called
     /// explicitly for bulk cleanup.
     pub fn free_node_ids(&mut self) -> usize {
-        // Collect dead entries
-        let mut dead_ids = Vec::new();
-        let mut dead_ptrs = Vec::new();
-
-        for (id, weak_ref) in self.nodes.iter() {
-            if weak_ref.upgrade().is_none() {
-                dead_ids.push(id);
-                dead_ptrs.push(weak_ref.as_ptr());
-            }
-        }
-
-        // Remove dead entries
+        let dead_ids = self
+            .nodes
+            .iter()
+            .filter(|(_, weak_ref)| weak_ref.strong_count() == 0)
+            .map(|(id, _)| id)
+            .collect_vec();
         for id in dead_ids {
-            self.nodes.remove(id);
-        }
-        for ptr in dead_ptrs {
-            self.ptr_to_id.remove(&ptr);
+            self.remove_entry(id);
         }
-
         self.nodes.len()
     }
 
-    /// Iterate over all live nodes in the registry.
-    pub fn iter(&self) -> impl Iterator<Item = (NodeId, RelRc<N, E>)> + '_ {
+    /// Iterate over all live nodes in the registry, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (S::Key, RelRc<N, E>)> + '_ {
         self.nodes
             .iter()
             .filter_map(|(id, weak_ref)| Some((id, weak_ref.upgrade()?)))
@@ -153,15 +515,204 @@ impl<N, E> Registry<N, E> {
     }
 
     /// Remove a node from the registry.
-    pub fn remove(&mut self, id: NodeId) {
-        // Node was dropped, clean up both maps
-        let weak_ref = self.nodes.remove(id);
-        if let Some(weak_ref) = weak_ref {
-            self.ptr_to_id.remove(&weak_ref.as_ptr());
+    ///
+    /// Does nothing if the node is pinned: the IDs of pinned nodes must not go
+    /// stale, so the node must be unpinned first, see [`Registry::unpin`].
+    pub fn remove(&mut self, id: S::Key) {
+        if self.pins.contains_key(&id) {
+            return;
+        }
+        self.remove_entry(id);
+    }
+
+    /// Move the nodes with the given IDs from the registry `from` into the
+    /// registry `to`.
+    ///
+    /// Returns the map from the IDs of the moved nodes in `from` to their IDs
+    /// in `to`. IDs of nodes that are not live in `from` are ignored, as are
+    /// nodes that the key strategy of `to` cannot assign a key to. Nodes that
+    /// were already in `to` keep their existing ID. The moved nodes are
+    /// removed from `from`, so that their drop is only observed by `to`.
+    ///
+    /// The returned map can be used to translate the IDs held by other data
+    /// structures, such as [`crate::EdgeId`]s and side tables. The IDs of the
+    /// moved nodes no longer resolve in `from`: [`crate::HistoryGraph`]s built
+    /// on `from` must be rebuilt on `to` with [`crate::HistoryGraph::remap`].
+    pub fn move_nodes<T: KeyStrategy>(
+        from: &Rc<RefCell<Self>>,
+        to: &Rc<RefCell<Registry<N, E, T>>>,
+        ids: impl IntoIterator<Item = S::Key>,
+    ) -> BTreeMap<S::Key, T::Key>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        let nodes = {
+            let from = from.borrow();
+            ids.into_iter()
+                .filter_map(|id| Some((id, from.get(id)?)))
+                .collect_vec()
+        };
+        if Rc::as_ptr(from) as *const () == Rc::as_ptr(to) as *const () {
+            // Moving nodes within a registry leaves them in place
+            let to = to.borrow();
+            return nodes
+                .into_iter()
+                .filter_map(|(id, node)| Some((id, to.get_id(&node)?)))
+                .collect();
+        }
+        let mut id_map = BTreeMap::new();
+        for (old_id, node) in nodes {
+            let Some(new_id) = node.try_register_in(to) else {
+                continue;
+            };
+            let pin = {
+                let mut from = from.borrow_mut();
+                let pin = from.pins.remove(&old_id);
+                from.remove(old_id);
+                pin
+            };
+            if let Some((_, n_pins)) = pin {
+                to.borrow_mut().pin_n(new_id, n_pins);
+            }
+            node.unregister_from(from);
+            id_map.insert(old_id, new_id);
+        }
+        Self::dispatch_events(from);
+        id_map
+    }
+
+    /// Move all live nodes of the registry `from` into the registry `to`.
+    ///
+    /// `from` is empty afterwards, unless the key strategy of `to` could not
+    /// assign keys to some of its nodes. See [`Registry::move_nodes`].
+    pub fn move_all<T: KeyStrategy>(
+        from: &Rc<RefCell<Self>>,
+        to: &Rc<RefCell<Registry<N, E, T>>>,
+    ) -> BTreeMap<S::Key, T::Key>
+    where
+        N: 'static,
+        E: 'static,
+    {
+        let ids = from.borrow().iter().map(|(id, _)| id).collect_vec();
+        let id_map = Self::move_nodes(from, to, ids);
+        from.borrow_mut().free_node_ids();
+        Self::dispatch_events(from);
+        id_map
+    }
+
+    /// Pin the node with the given ID, keeping it alive until it is unpinned.
+    ///
+    /// Pins are reference-counted: a node pinned several times must be
+    /// unpinned as many times to be released. Use this for IDs that are
+    /// handed out to external systems and must not go stale. Returns `false`
+    /// if there is no live node with the given ID.
+    pub fn pin(&mut self, id: S::Key) -> bool {
+        self.pin_n(id, 1)
+    }
+
+    fn pin_n(&mut self, id: S::Key, n_pins: usize) -> bool {
+        if let Some((_, count)) = self.pins.get_mut(&id) {
+            *count += n_pins;
+            return true;
+        }
+        let Some(node) = self.get(id) else {
+            return false;
+        };
+        self.pins.insert(id, (node, n_pins));
+        true
+    }
+
+    /// Remove one pin from the node with the given ID.
+    ///
+    /// Returns the number of remaining pins, or `None` if the node was not
+    /// pinned. Once the last pin is removed, the node is dropped if there are
+    /// no other references to it. This takes the shared registry, so that
+    /// the node is not dropped while the registry is borrowed.
+    pub fn unpin(registry: &Rc<RefCell<Self>>, id: S::Key) -> Option<usize> {
+        let mut registry = registry.borrow_mut();
+        let (_, count) = registry.pins.get_mut(&id)?;
+        *count -= 1;
+        if *count > 0 {
+            return Some(*count);
+        }
+        let (node, _) = registry.pins.remove(&id).expect("pinned node");
+        // Release the node once the registry is no longer borrowed
+        drop(registry);
+        drop(node);
+        Some(0)
+    }
+
+    /// Remove all pins of all nodes in the registry.
+    pub fn unpin_all(registry: &Rc<RefCell<Self>>) {
+        let pins = std::mem::take(&mut registry.borrow_mut().pins);
+        // Release the nodes once the registry is no longer borrowed
+        drop(pins);
+    }
+
+    /// The number of pins of the node with the given ID.
+    pub fn pin_count(&self, id: S::Key) -> usize {
+        self.pins.get(&id).map_or(0, |&(_, count)| count)
+    }
+
+    /// Iterate over the pinned nodes and their number of pins, in ID order.
+    pub fn pinned(&self) -> impl Iterator<Item = (S::Key, usize)> + '_ {
+        self.pins.iter().map(|(&id, &(_, count))| (id, count))
+    }
+
+    /// Register a callback to be called whenever a node of the registry is
+    /// dropped.
+    ///
+    /// This applies to the nodes that are tracked by the registry, i.e.
+    /// registered using [`RelRc::register_in`] or through a
+    /// [`crate::HistoryGraph`]. See [`RelRc::on_drop`] for when and how the
+    /// callbacks are called.
+    pub fn on_drop(&mut self, observer: impl Fn(&N, &[&E]) + 'static) {
+        self.drop_tracker
+            .0
+            .observers
+            .borrow_mut()
+            .push(Rc::new(observer));
+    }
+
+    /// Subscribe to the events of the registry.
+    ///
+    /// `subscriber` is called with a [`RegistryEvent`] whenever a node is
+    /// registered or its ID is freed, until the returned [`Subscription`] is
+    /// dropped. Events are delivered in order, once the registry is no longer
+    /// borrowed: subscribers may borrow the registry, register and drop
+    /// nodes.
+    ///
+    /// Registering nodes with [`RelRc::register_in`], dropping nodes and the
+    /// other operations that take a shared registry deliver events
+    /// automatically. Events caused by calling methods on a borrowed registry,
+    /// such as [`Registry::add_node`] or [`Registry::remove`], are delivered
+    /// by the next such operation, or by [`Registry::dispatch_events`].
+    pub fn subscribe(
+        &mut self,
+        subscriber: impl Fn(RegistryEvent<S::Key>) + 'static,
+    ) -> Subscription {
+        let subscriber: Rc<RegistrySubscriber<S::Key>> = Rc::new(subscriber);
+        self.subscribers
+            .subscribers
+            .push(Rc::downgrade(&subscriber));
+        Subscription {
+            _subscriber: Rc::new(subscriber),
         }
     }
 
-    pub(crate) fn as_slotmap(&self) -> &SlotMap<NodeId, RelWeak<N, E>> {
+    /// Remove the nodes that were dropped while the registry was borrowed, and
+    /// deliver the pending events of the registry to its subscribers.
+    ///
+    /// Does nothing if the registry is currently borrowed: the nodes are then
+    /// removed and the events delivered by the next call. See
+    /// [`Registry::subscribe`].
+    pub fn dispatch_events(registry: &Rc<RefCell<Self>>) {
+        dispatch_events::<N, E, _>(registry);
+    }
+
+    /// The nodes of the registry, by key.
+    pub(crate) fn nodes(&self) -> &S::Map<RelWeak<N, E>> {
         &self.nodes
     }
 }
@@ -176,8 +727,8 @@ impl<'r, N, E> FromIterator<&'r RelRc<N, E>> for Registry<N, E> {
     }
 }
 
-impl<N, E> From<Registry<N, E>> for Rc<RefCell<Registry<N, E>>> {
-    fn from(registry: Registry<N, E>) -> Self {
+impl<N, E, S: KeyStrategy> From<Registry<N, E, S>> for Rc<RefCell<Registry<N, E, S>>> {
+    fn from(registry: Registry<N, E, S>) -> Self {
         Rc::new(RefCell::new(registry))
     }
 }
@@ -209,6 +760,223 @@ mod tests {
         assert_eq!(id1, id2);
     }
 
+    #[test]
+    fn test_drop_observers() {
+        let registry = Rc::new(RefCell::new(Registry::<&str, usize>::new()));
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let dropped_clone = dropped.clone();
+        let registry_clone = registry.clone();
+        registry.borrow_mut().on_drop(move |&value, edges| {
+            dropped_clone.borrow_mut().push((value, edges.len()));
+            // Creating new nodes while a node is being dropped is allowed
+            if value == "child" {
+                let new_node = RelRc::new("new");
+                new_node.register_in(&registry_clone);
+            }
+        });
+
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_parents("child", [(parent.clone(), 1)]);
+        parent.register_in(&registry);
+        child.register_in(&registry);
+
+        let node_dropped = Rc::new(RefCell::new(None));
+        let node_dropped_clone = node_dropped.clone();
+        child.on_drop(move |&value, edges| {
+            node_dropped_clone.replace(Some((value, *edges[0])));
+        });
+
+        drop(parent);
+        assert!(dropped.borrow().is_empty());
+        drop(child);
+        assert_eq!(node_dropped.borrow().as_ref(), Some(&("child", 1)));
+        assert_eq!(
+            dropped.borrow().as_slice(),
+            [("child", 1), ("new", 0), ("parent", 0)]
+        );
+        assert!(registry.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_move_nodes() {
+        let from = Rc::new(RefCell::new(Registry::<&str, ()>::new()));
+        let to = Rc::new(RefCell::new(Registry::new()));
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let dropped_clone = dropped.clone();
+        to.borrow_mut()
+            .on_drop(move |&value, _| dropped_clone.borrow_mut().push(value));
+
+        let shared = RelRc::new("shared");
+        let moved = RelRc::with_parents("moved", [(shared.clone(), ())]);
+        let kept = RelRc::new("kept");
+        let [shared_id, moved_id, kept_id] = [&shared, &moved, &kept].map(|n| n.register_in(&from));
+        let shared_to_id = shared.register_in(&to);
+
+        let id_map = Registry::move_nodes(&from, &to, [shared_id, moved_id]);
+        assert_eq!(id_map.len(), 2);
+        assert_eq!(id_map[&shared_id], shared_to_id);
+        assert!(to.borrow().get(id_map[&moved_id]).unwrap().ptr_eq(&moved));
+        assert!(!from.borrow().contains(&moved));
+        assert_eq!(moved.n_registries(), 1);
+
+        let id_map = Registry::move_all(&from, &to);
+        assert_eq!(id_map.keys().copied().collect_vec(), [kept_id]);
+        assert!(from.borrow().is_empty());
+
+        drop((shared, moved, kept));
+        assert_eq!(dropped.borrow().as_slice(), ["moved", "shared", "kept"]);
+        assert!(to.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_pins() {
+        let registry = Rc::new(RefCell::new(Registry::<&str, ()>::new()));
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_parents("child", [(parent.clone(), ())]);
+        let [parent_id, child_id] = [&parent, &child].map(|n| n.register_in(&registry));
+
+        assert!(registry.borrow_mut().pin(child_id));
+        assert!(registry.borrow_mut().pin(child_id));
+        assert_eq!(registry.borrow().pin_count(child_id), 2);
+        assert_eq!(registry.borrow().pinned().collect_vec(), [(child_id, 2)]);
+
+        // Pinned nodes and their ancestors outlive all handles
+        drop((parent, child));
+        registry.borrow_mut().remove(child_id);
+        assert!(registry.borrow().contains_id(parent_id));
+        assert!(registry.borrow().contains_id(child_id));
+
+        // A clone carries its own pins
+        let clone = Rc::new(RefCell::new(registry.borrow().clone()));
+        assert_eq!(clone.borrow().pin_count(child_id), 2);
+        Registry::unpin_all(&clone);
+        assert!(clone.borrow().contains_id(child_id));
+
+        assert_eq!(Registry::unpin(&registry, child_id), Some(1));
+        assert!(registry.borrow().contains_id(child_id));
+        assert_eq!(Registry::unpin(&registry, child_id), Some(0));
+        assert!(registry.borrow().is_empty());
+        assert_eq!(Registry::unpin(&registry, child_id), None);
+        assert!(!registry.borrow_mut().pin(child_id));
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let registry = Rc::new(RefCell::new(Registry::<&str, ()>::new()));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_clone = events.clone();
+        let registry_clone = registry.clone();
+        let subscription = registry.borrow_mut().subscribe(move |event| {
+            // The registry is not borrowed while events are delivered
+            let len = registry_clone.borrow().len();
+            events_clone.borrow_mut().push((event, len));
+        });
+
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_parents("child", [(parent.clone(), ())]);
+        let parent_id = parent.register_in(&registry);
+        let child_id = registry.borrow_mut().add_node(&child);
+        assert_eq!(events.borrow().len(), 1);
+        Registry::dispatch_events(&registry);
+        drop((parent, child));
+        assert_eq!(
+            events.borrow().as_slice(),
+            [
+                (RegistryEvent::Registered(parent_id), 1),
+                (RegistryEvent::Registered(child_id), 2),
+                // `child` was added without `register_in`: its ID is not freed
+                (RegistryEvent::Freed(parent_id), 1),
+            ]
+        );
+
+        drop(subscription);
+        RelRc::<_, ()>::new("other").register_in(&registry);
+        assert_eq!(events.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_drop_while_borrowed() {
+        let registry = Rc::new(RefCell::new(Registry::<&str, ()>::new()));
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let dropped_clone = dropped.clone();
+        registry
+            .borrow_mut()
+            .on_drop(move |&value, _| dropped_clone.borrow_mut().push(value));
+        let a = RelRc::new("a");
+        let b = RelRc::new("b");
+        let [a_id, b_id] = [&a, &b].map(|n| n.register_in(&registry));
+
+        // Drop and unwrap the nodes from a subscriber, while the registry is
+        // borrowed
+        let stash = Rc::new(RefCell::new(vec![a, b]));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let (registry_clone, stash_clone) = (registry.clone(), stash.clone());
+        let events_clone = events.clone();
+        let _subscription = registry.borrow_mut().subscribe(move |event| {
+            let _registry = registry_clone.borrow();
+            for node in stash_clone.take() {
+                if node.value() == &"a" {
+                    drop(node);
+                } else {
+                    assert!(node.into_inner().is_some());
+                }
+            }
+            events_clone.borrow_mut().push(event);
+        });
+        let c = RelRc::new("c");
+        let c_id = c.register_in(&registry);
+
+        // The nodes are removed once the registry is released
+        assert_eq!(dropped.borrow().as_slice(), ["a"]);
+        assert_eq!(registry.borrow().len(), 1);
+        assert!(registry.borrow().contains(&c));
+        assert_eq!(
+            events.borrow().as_slice(),
+            [
+                RegistryEvent::Registered(c_id),
+                RegistryEvent::Freed(a_id),
+                RegistryEvent::Freed(b_id),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_strategies() {
+        let sequential = Rc::new(RefCell::new(Registry::<&str, (), _>::with_keys(
+            SequentialKeys,
+        )));
+        let nodes = [RelRc::new("a"), RelRc::new("b")];
+        assert_eq!(nodes[0].register_in(&sequential), 0);
+        assert_eq!(nodes[1].try_register_with_key(&sequential, 10), Some(10));
+        assert_eq!(RelRc::new("c").register_in(&sequential), 11);
+
+        let supplied = Rc::new(RefCell::new(Registry::<&str, (), _>::with_keys(
+            SuppliedKeys::<&str>::default(),
+        )));
+        assert_eq!(nodes[0].try_register_in(&supplied), None);
+        assert_eq!(nodes[0].try_register_with_key(&supplied, "a"), Some("a"));
+        // The key is held by a live node
+        assert_eq!(nodes[1].try_register_with_key(&supplied, "a"), None);
+        assert_eq!(nodes[0].registries::<SuppliedKeys<&str>>().len(), 1);
+
+        let content = Rc::new(RefCell::new(Registry::<&str, (), _>::with_keys(
+            ContentKeys,
+        )));
+        let hashed = RelRc::new_hashed::<crate::hash_id::Fnv1a>("a");
+        assert_eq!(nodes[0].try_register_in(&content), None);
+        assert_eq!(hashed.try_register_in(&content), hashed.hash_id());
+        // Supplied keys must be the hash of the node
+        let other = RelRc::new_hashed::<crate::hash_id::Fnv1a>("b");
+        let other_hash = other.hash_id().unwrap();
+        let fresh = RelRc::new_hashed::<crate::hash_id::Fnv1a>("c");
+        assert_eq!(fresh.try_register_with_key(&content, other_hash), None);
+        assert_eq!(nodes[0].try_register_with_key(&content, other_hash), None);
+        assert_eq!(
+            other.try_register_with_key(&content, other_hash),
+            Some(other_hash)
+        );
+    }
+
     #[test]
     fn test_get_id() {
         let mut registry = Registry::<&str, ()>::new();
@@ -0,0 +1,368 @@
+//! Strategies to assign keys to the nodes of a [`Registry`].
+//!
+//! [`Registry`]: super::Registry
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use derive_where::derive_where;
+use slotmap_fork_lmondada::{new_key_type, SlotMap};
+
+use crate::{RelRc, RelRcHash};
+
+new_key_type! {
+    /// A unique identifier for a node in the registry.
+    pub struct NodeId;
+}
+
+/// A strategy to assign keys to the nodes of a [`Registry`].
+///
+/// The strategy also determines how the nodes of a registry are stored: its
+/// [`KeyStrategy::Map`] holds the values of the registry by key, along with
+/// the state of the strategy, such as the keys that are in use. The same map
+/// is used for serialized registries, so that deserialized registries keep
+/// assigning fresh keys.
+///
+/// [`Registry`]: super::Registry
+pub trait KeyStrategy: Default + Clone + Debug + 'static {
+    /// The keys of the registry.
+    type Key: Copy + Eq + Ord + Hash + Debug + 'static;
+
+    /// The map from keys to values of a registry.
+    type Map<V>: KeyMap<Self::Key, V>;
+
+    /// Create an empty map.
+    fn new_map<V>(&self) -> Self::Map<V>;
+
+    /// Insert a value for a node that is being registered, with the key that
+    /// the strategy assigns to the node.
+    ///
+    /// Returns the key and the value previously stored with it, if any.
+    /// Returns `None` without inserting the value if the strategy cannot
+    /// assign a key to the node, in which case a key must be supplied, see
+    /// [`Registry::try_add_node_with_key`](super::Registry::try_add_node_with_key).
+    fn insert_new<N, E, V>(
+        map: &mut Self::Map<V>,
+        node: &RelRc<N, E>,
+        value: V,
+    ) -> Option<(Self::Key, Option<V>)>;
+
+    /// Insert a value for a node that is being registered, with a key
+    /// supplied by the user.
+    ///
+    /// Returns the value previously stored with the key, if any, or gives
+    /// the value back if the strategy does not accept the key for the node.
+    fn insert_with_key<N, E, V>(
+        map: &mut Self::Map<V>,
+        node: &RelRc<N, E>,
+        key: Self::Key,
+        value: V,
+    ) -> Result<Option<V>, V>;
+
+    /// Map the values of a map, keeping their keys and the state of the
+    /// strategy.
+    fn map_values<V, U>(map: &Self::Map<V>, f: impl FnMut(Self::Key, &V) -> U) -> Self::Map<U>;
+}
+
+/// A map from keys to values, see [`KeyStrategy::Map`].
+///
+/// Values are inserted through the [`KeyStrategy`] of the map.
+pub trait KeyMap<K, V>: IntoIterator<Item = (K, V)> {
+    /// The value with the given key.
+    fn get(&self, key: K) -> Option<&V>;
+
+    /// Remove the value with the given key and return it.
+    fn remove(&mut self, key: K) -> Option<V>;
+
+    /// The number of values in the map.
+    fn len(&self) -> usize;
+
+    /// Whether the map is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the keys and values of the map, in key order.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (K, &'a V)>
+    where
+        V: 'a;
+
+    /// Only keep the values for which `f` returns `true`.
+    fn retain(&mut self, f: impl FnMut(K, &mut V) -> bool);
+}
+
+/// Keys allocated by a slot map: [`NodeId`]s.
+///
+/// Freed keys are reused with a new version, so that stale keys never refer
+/// to a new node. This is the default key strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotKeys;
+
+impl KeyStrategy for SlotKeys {
+    type Key = NodeId;
+    type Map<V> = SlotMap<NodeId, V>;
+
+    fn new_map<V>(&self) -> SlotMap<NodeId, V> {
+        SlotMap::with_key()
+    }
+
+    fn insert_new<N, E, V>(
+        map: &mut SlotMap<NodeId, V>,
+        _node: &RelRc<N, E>,
+        value: V,
+    ) -> Option<(NodeId, Option<V>)> {
+        Some((map.insert(value), None))
+    }
+
+    fn insert_with_key<N, E, V>(
+        _map: &mut SlotMap<NodeId, V>,
+        _node: &RelRc<N, E>,
+        _key: NodeId,
+        value: V,
+    ) -> Result<Option<V>, V> {
+        // Node IDs can only be allocated by the slot map
+        Err(value)
+    }
+
+    fn map_values<V, U>(
+        map: &SlotMap<NodeId, V>,
+        mut f: impl FnMut(NodeId, &V) -> U,
+    ) -> SlotMap<NodeId, U> {
+        map.map(|key, value| f(key, value))
+    }
+}
+
+impl<V> KeyMap<NodeId, V> for SlotMap<NodeId, V> {
+    fn get(&self, key: NodeId) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn remove(&mut self, key: NodeId) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (NodeId, &'a V)>
+    where
+        V: 'a,
+    {
+        self.iter()
+    }
+
+    fn retain(&mut self, f: impl FnMut(NodeId, &mut V) -> bool) {
+        self.retain(f)
+    }
+}
+
+/// Sequential `u64` keys, in registration order.
+///
+/// Keys are never reused, and are reproducible across runs if nodes are
+/// registered in the same order. Supplied keys are accepted, and all keys
+/// assigned after them are larger.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequentialKeys;
+
+/// The map of [`SequentialKeys`]: the values by key, and the next key to
+/// assign.
+#[derive(Debug, Clone)]
+#[derive_where(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequentialMap<V> {
+    next: u64,
+    values: BTreeMap<u64, V>,
+}
+
+impl KeyStrategy for SequentialKeys {
+    type Key = u64;
+    type Map<V> = SequentialMap<V>;
+
+    fn new_map<V>(&self) -> SequentialMap<V> {
+        SequentialMap::default()
+    }
+
+    fn insert_new<N, E, V>(
+        map: &mut SequentialMap<V>,
+        _node: &RelRc<N, E>,
+        value: V,
+    ) -> Option<(u64, Option<V>)> {
+        let key = map.next;
+        map.next += 1;
+        Some((key, map.values.insert(key, value)))
+    }
+
+    fn insert_with_key<N, E, V>(
+        map: &mut SequentialMap<V>,
+        _node: &RelRc<N, E>,
+        key: u64,
+        value: V,
+    ) -> Result<Option<V>, V> {
+        map.next = map.next.max(key.saturating_add(1));
+        Ok(map.values.insert(key, value))
+    }
+
+    fn map_values<V, U>(
+        map: &SequentialMap<V>,
+        mut f: impl FnMut(u64, &V) -> U,
+    ) -> SequentialMap<U> {
+        SequentialMap {
+            next: map.next,
+            values: map.values.iter().map(|(&k, v)| (k, f(k, v))).collect(),
+        }
+    }
+}
+
+impl<V> IntoIterator for SequentialMap<V> {
+    type Item = (u64, V);
+    type IntoIter = std::collections::btree_map::IntoIter<u64, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+impl<V> KeyMap<u64, V> for SequentialMap<V> {
+    fn get(&self, key: u64) -> Option<&V> {
+        self.values.get(&key)
+    }
+
+    fn remove(&mut self, key: u64) -> Option<V> {
+        self.values.remove(&key)
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (u64, &'a V)>
+    where
+        V: 'a,
+    {
+        self.values.iter().map(|(&k, v)| (k, v))
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(u64, &mut V) -> bool) {
+        self.values.retain(|&k, v| f(k, v))
+    }
+}
+
+/// Keys of type `K` supplied by the user when registering nodes.
+///
+/// Nodes can only be registered with a key, see
+/// [`RelRc::try_register_with_key`].
+#[derive(Debug)]
+#[derive_where(Clone, Copy, Default)]
+pub struct SuppliedKeys<K>(PhantomData<fn() -> K>);
+
+impl<K: Copy + Eq + Ord + Hash + Debug + 'static> KeyStrategy for SuppliedKeys<K> {
+    type Key = K;
+    type Map<V> = BTreeMap<K, V>;
+
+    fn new_map<V>(&self) -> BTreeMap<K, V> {
+        BTreeMap::new()
+    }
+
+    fn insert_new<N, E, V>(
+        _map: &mut BTreeMap<K, V>,
+        _node: &RelRc<N, E>,
+        _value: V,
+    ) -> Option<(K, Option<V>)> {
+        None
+    }
+
+    fn insert_with_key<N, E, V>(
+        map: &mut BTreeMap<K, V>,
+        _node: &RelRc<N, E>,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, V> {
+        Ok(map.insert(key, value))
+    }
+
+    fn map_values<V, U>(map: &BTreeMap<K, V>, f: impl FnMut(K, &V) -> U) -> BTreeMap<K, U> {
+        map_btree_values(map, f)
+    }
+}
+
+/// Content-addressed keys: the [`RelRcHash`] of the nodes.
+///
+/// Only nodes with a hash can be registered, see
+/// [`InnerData::hash_id`](crate::node::InnerData::hash_id). At most one node
+/// per hash can be registered at a time. Supplied keys are only accepted if
+/// they are the hash of the node.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentKeys;
+
+impl KeyStrategy for ContentKeys {
+    type Key = RelRcHash;
+    type Map<V> = BTreeMap<RelRcHash, V>;
+
+    fn new_map<V>(&self) -> BTreeMap<RelRcHash, V> {
+        BTreeMap::new()
+    }
+
+    fn insert_new<N, E, V>(
+        map: &mut BTreeMap<RelRcHash, V>,
+        node: &RelRc<N, E>,
+        value: V,
+    ) -> Option<(RelRcHash, Option<V>)> {
+        let key = node.hash_id()?;
+        Some((key, map.insert(key, value)))
+    }
+
+    fn insert_with_key<N, E, V>(
+        map: &mut BTreeMap<RelRcHash, V>,
+        node: &RelRc<N, E>,
+        key: RelRcHash,
+        value: V,
+    ) -> Result<Option<V>, V> {
+        // The key of a node is its hash
+        if node.hash_id() != Some(key) {
+            return Err(value);
+        }
+        Ok(map.insert(key, value))
+    }
+
+    fn map_values<V, U>(
+        map: &BTreeMap<RelRcHash, V>,
+        f: impl FnMut(RelRcHash, &V) -> U,
+    ) -> BTreeMap<RelRcHash, U> {
+        map_btree_values(map, f)
+    }
+}
+
+impl<K: Copy + Ord, V> KeyMap<K, V> for BTreeMap<K, V> {
+    fn get(&self, key: K) -> Option<&V> {
+        self.get(&key)
+    }
+
+    fn remove(&mut self, key: K) -> Option<V> {
+        self.remove(&key)
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (K, &'a V)>
+    where
+        V: 'a,
+    {
+        self.iter().map(|(&k, v)| (k, v))
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(K, &mut V) -> bool) {
+        self.retain(|&k, v| f(k, v))
+    }
+}
+
+fn map_btree_values<K: Copy + Ord, V, U>(
+    map: &BTreeMap<K, V>,
+    mut f: impl FnMut(K, &V) -> U,
+) -> BTreeMap<K, U> {
+    map.iter().map(|(&k, v)| (k, f(k, v))).collect()
+}
@@ -0,0 +1,237 @@
+//! Diagnostics on why [`RelRc`] objects are kept alive.
+//!
+//! An object is alive as long as it is strongly referenced, either by a
+//! handle held outside of the graph of objects, or by the strong incoming
+//! edges of its children. Use [`RelRc::retainers`] to find the objects whose
+//! external handles keep a given object alive, and
+//! [`RelRc::retained_size`] to find what would be freed by dropping a handle.
+
+use fxhash::{FxHashMap, FxHashSet};
+use itertools::Itertools;
+
+use crate::registry::KeyStrategy;
+use crate::{HistoryGraph, Registry, RelRc, RelWeak};
+
+/// An object with external handles that keeps another object alive, see
+/// [`RelRc::retainers`].
+#[derive(Debug, Clone)]
+pub struct Retainer<N, E> {
+    /// The retaining object: the retained object itself or one of its
+    /// descendants.
+    ///
+    /// A weak reference, so that the diagnostic does not itself retain the
+    /// object.
+    pub node: RelWeak<N, E>,
+    /// The number of strong references to the object that are not held by
+    /// the incoming edges of its children, i.e. by external handles.
+    pub external_count: usize,
+}
+
+/// The objects that would be freed by dropping a handle, see
+/// [`RelRc::retained_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RetainedSize {
+    /// The number of objects that would be freed.
+    pub nodes: usize,
+    /// The total size of the objects that would be freed, as computed by the
+    /// user-supplied size function.
+    pub size: usize,
+}
+
+impl<N, E> RelRc<N, E> {
+    /// The objects that would be freed if this handle was dropped.
+    ///
+    /// The object is freed if this is its last external handle, see
+    /// [`RelRc::retainers`]. Its ancestors are then freed in turn if they are
+    /// only kept alive by freed objects. The size of every freed object is
+    /// given by `size_fn`, called with the value of the object and the values
+    /// of its incoming edges.
+    pub fn retained_size(&self, size_fn: impl Fn(&N, &[&E]) -> usize) -> RetainedSize {
+        retained_size(self, 0, size_fn)
+    }
+
+    /// The objects whose external handles keep this object alive.
+    ///
+    /// An object is retained by its own handles and by the handles of all
+    /// descendants that it can be reached from through strong edges. Returns
+    /// every such object with a non-zero count of external handles, in
+    /// breadth-first order from self. The handle on which this is called is
+    /// counted as an external handle of self.
+    ///
+    /// External handles are all strong references that are not incoming
+    /// edges of other objects: `RelRc` handles held by the application, but
+    /// also the nodes of [`crate::HistoryGraph`]s and the pins of
+    /// [`crate::Registry`]s. This does not require the objects to be
+    /// registered.
+    pub fn retainers(&self) -> Vec<Retainer<N, E>> {
+        self.strong_descendants()
+            .into_iter()
+            .filter_map(|node| {
+                let external_count = external_count(&node)?;
+                (external_count > 0).then_some(Retainer {
+                    node,
+                    external_count,
+                })
+            })
+            .collect()
+    }
+
+    /// Self and all descendants reachable from self through strong edges, in
+    /// breadth-first order.
+    ///
+    /// Returns weak references, so that the strong counts of the objects are
+    /// not affected.
+    pub(crate) fn strong_descendants(&self) -> Vec<RelWeak<N, E>> {
+        let mut seen = FxHashSet::from_iter([self.as_ptr()]);
+        let mut nodes = vec![self.downgrade()];
+        let mut next = 0;
+        while let Some(weak) = nodes.get(next).cloned() {
+            next += 1;
+            let Some(node) = weak.upgrade() else {
+                continue;
+            };
+            for edge in node.all_outgoing() {
+                if !edge.is_weak() && seen.insert(edge.target().as_ptr()) {
+                    nodes.push(edge.target().downgrade());
+                }
+            }
+        }
+        nodes
+    }
+}
+
+/// The number of strong references to an object that are not held by the
+/// incoming edges of its children, or `None` if it is no longer alive.
+pub(crate) fn external_count<N, E>(node: &RelWeak<N, E>) -> Option<usize> {
+    // Count before upgrading, so that the upgraded handle is not counted
+    let strong_count = node.strong_count();
+    let node = node.upgrade()?;
+    let internal_count = node
+        .all_outgoing()
+        .iter()
+        .filter(|edge| !edge.is_weak())
+        .count();
+    Some(strong_count - internal_count)
+}
+
+/// The objects that would be freed if one handle to `node` was dropped,
+/// ignoring `n_temporaries` other handles to `node` that are about to be
+/// dropped as well.
+fn retained_size<N, E>(
+    node: &RelRc<N, E>,
+    n_temporaries: usize,
+    size_fn: impl Fn(&N, &[&E]) -> usize,
+) -> RetainedSize {
+    // The strong references left to every visited object. All objects are
+    // borrowed from `node` and its incoming edges, so that no handles are
+    // created.
+    let mut remaining = FxHashMap::default();
+    let mut freed = RetainedSize::default();
+    let mut stack = vec![(node, n_temporaries + 1)];
+    while let Some((node, n_dropped)) = stack.pop() {
+        let count = remaining
+            .entry(node.as_ptr())
+            .or_insert_with(|| node.downgrade().strong_count());
+        *count -= n_dropped;
+        if *count > 0 {
+            continue;
+        }
+        let edge_values = node.all_incoming().iter().map(|e| e.value()).collect_vec();
+        freed.nodes += 1;
+        freed.size += size_fn(node.value(), &edge_values);
+        stack.extend(node.all_parents().map(|parent| (parent, 1)));
+    }
+    freed
+}
+
+impl<N, E, S: KeyStrategy> HistoryGraph<N, E, S> {
+    /// The objects that would be freed if the graph dropped its handle to the
+    /// node with the given ID.
+    ///
+    /// The handles of the graph to the other nodes are kept. Returns `None`
+    /// if the node is not in the graph. See [`RelRc::retained_size`].
+    pub fn retained_size(
+        &self,
+        node_id: S::Key,
+        size_fn: impl Fn(&N, &[&E]) -> usize,
+    ) -> Option<RetainedSize> {
+        Some(self.get_node(node_id)?.retained_size(size_fn))
+    }
+}
+
+impl<N, E, S: KeyStrategy> Registry<N, E, S> {
+    /// The objects that would be freed if one external handle to the node
+    /// with the given ID was dropped, e.g. a pin.
+    ///
+    /// Returns `None` if there is no live node with the given ID. See
+    /// [`RelRc::retained_size`].
+    pub fn retained_size(
+        &self,
+        id: S::Key,
+        size_fn: impl Fn(&N, &[&E]) -> usize,
+    ) -> Option<RetainedSize> {
+        let node = self.get(id)?;
+        Some(retained_size(&node, 1, size_fn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::EdgeSource;
+
+    use super::*;
+
+    #[test]
+    fn test_retainers() {
+        let root = RelRc::<_, ()>::new("root");
+        let middle = RelRc::with_parents("middle", [(root.clone(), ())]);
+        let leaf = RelRc::with_parents("leaf", [(middle.clone(), ()), (root.clone(), ())]);
+        let weak_child = RelRc::with_sources("weak", [(EdgeSource::Weak(root.downgrade()), ())]);
+        drop(middle);
+
+        let retainers = |node: &RelRc<&'static str, ()>| {
+            node.retainers()
+                .into_iter()
+                .map(|r| (*r.node.upgrade().unwrap().value(), r.external_count))
+                .collect_vec()
+        };
+        // The weak child does not retain the root
+        assert_eq!(retainers(&root), [("root", 1), ("leaf", 1)]);
+
+        // Pins are external handles
+        let registry = Rc::new(RefCell::new(Registry::new()));
+        let leaf_id = leaf.register_in(&registry);
+        registry.borrow_mut().pin(leaf_id);
+        drop((root, weak_child));
+        let root_ref = leaf.all_ancestors().find(|n| *n.value() == "root").unwrap();
+        assert_eq!(retainers(root_ref), [("leaf", 2)]);
+    }
+
+    #[test]
+    fn test_retained_size() {
+        let root = RelRc::<_, usize>::new("root");
+        let left = RelRc::with_parents("left", [(root.clone(), 1)]);
+        let right = RelRc::with_parents("right", [(root.clone(), 2)]);
+        let tip = RelRc::with_parents("tip", [(left.clone(), 3), (right.clone(), 4)]);
+        let size = |value: &&str, edges: &[&usize]| value.len() + edges.len();
+
+        let graph = HistoryGraph::from_nodes([left.clone()]);
+        let left_id = graph.all_node_ids().next().unwrap();
+        drop((root, left, right));
+        // The graph holds a handle to the left node, and thus to the root
+        assert_eq!(tip.retained_size(size), RetainedSize { nodes: 2, size: 11 });
+        assert_eq!(
+            graph.retained_size(left_id, size),
+            Some(RetainedSize::default())
+        );
+
+        drop(tip);
+        let expected = RetainedSize { nodes: 2, size: 9 };
+        assert_eq!(graph.retained_size(left_id, size), Some(expected));
+        let registry = graph.registry().borrow();
+        assert_eq!(registry.retained_size(left_id, size), Some(expected));
+    }
+}
@@ -1,106 +1,182 @@
 //! Serialization and deserialization of [`RelRc`] objects.
 
-use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
-use derive_more::{From, Into};
 use fxhash::FxHashSet;
 use itertools::Itertools;
-use slotmap_fork_lmondada::{SecondaryMap, SlotMap};
 
-use crate::{HistoryGraph, NodeId, Registry, RelRc};
+use crate::registry::{KeyMap, KeyStrategy, SlotKeys, UnassignedKey};
+use crate::{EdgeSource, HistoryGraph, NodeId, Registry, RelRc, RelRcHash};
 
 /// A serializable representation of a [`RelRc`] object.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct SerializedRelRc<N, E> {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SerializedNodes<N, E, S>: serde::Serialize, S::Key: serde::Serialize",
+        deserialize = "SerializedNodes<N, E, S>: serde::Deserialize<'de>, S::Key: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SerializedRelRc<N, E, S: KeyStrategy = SlotKeys> {
     /// The ID of the current node.
-    pub id: NodeId,
+    pub id: S::Key,
     /// The ancestors of the current node.
-    pub ancestors_graph: SerializedHistoryGraph<N, E>,
+    pub ancestors_graph: SerializedHistoryGraph<N, E, S>,
 }
 
 /// A serializable representation of a [`HistoryGraph`] object.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct SerializedHistoryGraph<N, E> {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SerializedNodes<N, E, S>: serde::Serialize, S::Key: serde::Serialize",
+        deserialize = "SerializedNodes<N, E, S>: serde::Deserialize<'de>, S::Key: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SerializedHistoryGraph<N, E, S: KeyStrategy = SlotKeys> {
     /// The node IDs of the graph.
-    pub nodes: BTreeSet<NodeId>,
+    pub nodes: BTreeSet<S::Key>,
     /// All nodes required to reconstruct the graph (i.e. the nodes
     /// in `nodes` and their ancestors).
-    pub registry: SerializedRegistry<N, E>,
+    pub registry: SerializedRegistry<N, E, S>,
 }
 
 /// A serializable representation of the inner data of a [`RelRc`] object.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct SerializedInnerData<N, E> {
+pub struct SerializedInnerData<N, E, K = NodeId> {
     /// The value of the node.
     pub value: N,
     /// The incoming edges of the node.
-    pub incoming: Vec<(NodeId, E)>,
+    ///
+    /// The source of a weak edge is `None` if it was not serialized, in which
+    /// case the edge is deserialized as a dangling edge.
+    pub incoming: Vec<(Option<K>, E)>,
+    /// The positions of the weak edges in `incoming`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeSet::is_empty")
+    )]
+    pub weak: BTreeSet<usize>,
+    /// The content-addressed hash of the node, if it has one.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub hash_id: Option<RelRcHash>,
 }
 
-impl<N, E> SerializedInnerData<N, E> {
+impl<N, E, K> SerializedInnerData<N, E, K> {
     /// Map the value of the node.
-    pub fn map_value<M>(self, f: impl FnOnce(N) -> M) -> SerializedInnerData<M, E> {
+    pub fn map_value<M>(self, f: impl FnOnce(N) -> M) -> SerializedInnerData<M, E, K> {
         SerializedInnerData {
             value: f(self.value),
             incoming: self.incoming,
+            weak: self.weak,
+            hash_id: self.hash_id,
         }
     }
 }
 
-impl<N: Clone, E: Clone> SerializedInnerData<N, E> {
-    fn serialize_inner_data(
-        node_id: NodeId,
-        registry: &Registry<N, E>,
-    ) -> SerializedInnerData<N, E> {
+impl<N: Clone, E: Clone, K: Copy> SerializedInnerData<N, E, K> {
+    fn serialize_inner_data<S: KeyStrategy<Key = K>>(
+        node_id: K,
+        registry: &Registry<N, E, S>,
+    ) -> Self {
         let node = registry.get(node_id).expect("valid node");
-        let parent_ids = node
-            .all_parents()
-            .map(|n| registry.get_id(n).expect("valid node"));
         let value = node.value().clone();
-        let incoming = parent_ids
-            .zip(node.all_incoming())
-            .map(|(parent_id, edge)| {
-                let edge_value = edge.value().clone();
-                (parent_id, edge_value)
+        let incoming = node
+            .all_incoming()
+            .iter()
+            .map(|edge| {
+                let parent_id = match edge.source() {
+                    Some(parent) => Some(registry.get_id(parent).expect("valid node")),
+                    None => edge.upgrade_source().and_then(|p| registry.get_id(&p)),
+                };
+                (parent_id, edge.value().clone())
             })
             .collect();
-        Self { value, incoming }
+        let weak = node
+            .all_incoming()
+            .iter()
+            .positions(|edge| edge.is_weak())
+            .collect();
+        Self {
+            value,
+            incoming,
+            weak,
+            hash_id: node.hash_id(),
+        }
     }
 }
 
+/// Serialized nodes, by key.
+type SerializedNodes<N, E, S> =
+    <S as KeyStrategy>::Map<SerializedInnerData<N, E, <S as KeyStrategy>::Key>>;
+
 /// A serializable representation of a [`Registry`] object.
 ///
 /// You typically do not want to use this type directly, as registries shouldn't
 /// be serialised on their own. (They only keep weak references to nodes).
-#[derive(Debug, Clone, From, Into)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct SerializedRegistry<N, E> {
-    /// The nodes in the registry and their serialized data.
-    pub nodes: SlotMap<NodeId, SerializedInnerData<N, E>>,
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SerializedNodes<N, E, S>: serde::Serialize",
+        deserialize = "SerializedNodes<N, E, S>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SerializedRegistry<N, E, S: KeyStrategy = SlotKeys> {
+    /// The nodes in the registry and their serialized data, by key.
+    ///
+    /// The map of the key strategy also records the keys in use, so that new
+    /// keys do not collide with the serialized ones.
+    pub nodes: SerializedNodes<N, E, S>,
 }
 
-impl<N: Clone, E: Clone> SerializedRegistry<N, E> {
+impl<N: Clone, E: Clone, S: KeyStrategy> SerializedRegistry<N, E, S> {
     /// Map the value of the nodes in the registry.
-    pub fn map_nodes<M>(&self, mut f: impl FnMut(N) -> M) -> SerializedRegistry<M, E> {
+    pub fn map_nodes<M>(&self, mut f: impl FnMut(N) -> M) -> SerializedRegistry<M, E, S> {
+        SerializedRegistry {
+            nodes: S::map_values(&self.nodes, |_, v| v.clone().map_value(&mut f)),
+        }
+    }
+}
+
+impl<N: Clone, E: Clone, S: KeyStrategy> Clone for SerializedRegistry<N, E, S> {
+    fn clone(&self) -> Self {
         SerializedRegistry {
-            nodes: self.nodes.map(|_, v| v.clone().map_value(&mut f)),
+            nodes: S::map_values(&self.nodes, |_, v| v.clone()),
         }
     }
 }
 
-impl<N, E> Registry<N, E> {
+impl<N: std::fmt::Debug, E: std::fmt::Debug, S: KeyStrategy> std::fmt::Debug
+    for SerializedRegistry<N, E, S>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerializedRegistry")
+            .field("nodes", &self.nodes.iter().collect::<BTreeMap<_, _>>())
+            .finish()
+    }
+}
+
+impl<N, E, S: KeyStrategy> Registry<N, E, S> {
     /// Convert a [`Registry`] object to its serializable format.
-    pub fn to_serialized(&self) -> SerializedRegistry<N, E>
+    pub fn to_serialized(&self) -> SerializedRegistry<N, E, S>
     where
         N: Clone,
         E: Clone,
     {
-        let nodes = self
-            .as_slotmap()
-            .map(|k, _| SerializedInnerData::serialize_inner_data(k, self));
+        let nodes = S::map_values(self.nodes(), |k, _| {
+            SerializedInnerData::serialize_inner_data(k, self)
+        });
         SerializedRegistry { nodes }
     }
 
@@ -110,139 +186,188 @@ impl<N, E> Registry<N, E> {
     /// Return the deserialised [`Registry`] object alongside the deserialised
     /// nodes. The registry only contains weak references to the nodes.
     pub fn from_serialized(
-        serialized: SerializedRegistry<N, E>,
-    ) -> (Self, SecondaryMap<NodeId, RelRc<N, E>>) {
-        let nodes_set = serialized.nodes.map(|_, _| ());
-        let nodes = deserialize_nodes(serialized.nodes);
-        let registry = nodes_set.map(|k, ()| nodes.get(k).expect("invalid node_id").downgrade());
-
-        (Self::from_slotmap(registry), nodes)
+        serialized: SerializedRegistry<N, E, S>,
+    ) -> (Self, BTreeMap<S::Key, RelRc<N, E>>) {
+        // Keep the keys of the serialized map, along with the state of the key
+        // strategy
+        let keys = S::map_values(&serialized.nodes, |_, _| ());
+        let nodes = deserialize_nodes(serialized.nodes.into_iter().collect());
+        let registry = S::map_values(&keys, |k, _| nodes[&k].downgrade());
+
+        (Self::from_nodes(registry), nodes)
     }
 }
 
-impl<N, E> RelRc<N, E> {
+impl<N: 'static, E: 'static> RelRc<N, E> {
     /// Convert a [`RelRc`] object to a serializable format.
     ///
-    /// Requires a [`Registry`] to identify serialized nodes using IDs.
-    pub fn to_serialized(
+    /// Requires a [`Registry`] to identify serialized nodes using IDs. The
+    /// object and its ancestors are registered in the registry.
+    ///
+    /// Returns an [`UnassignedKey`] error if the key strategy of the registry
+    /// cannot assign a key to one of the nodes.
+    pub fn to_serialized<S: KeyStrategy>(
         &self,
-        registry: impl Into<Rc<RefCell<Registry<N, E>>>>,
-    ) -> SerializedRelRc<N, E>
+        registry: impl Into<Rc<RefCell<Registry<N, E, S>>>>,
+    ) -> Result<SerializedRelRc<N, E, S>, UnassignedKey>
     where
         N: Clone,
         E: Clone,
     {
         let mut history = HistoryGraph::with_registry(registry);
-        let curr_id = history.insert_ancestors(self.clone());
-        SerializedRelRc {
+        let curr_id = history
+            .try_insert_ancestors(self.clone())
+            .ok_or(UnassignedKey)?;
+        Ok(SerializedRelRc {
             id: curr_id,
-            ancestors_graph: history.to_serialized(),
-        }
+            ancestors_graph: history.to_serialized()?,
+        })
     }
 
     /// Convert a serializable representation of a [`RelRc`] object back to a
     /// [`RelRc`] object.
-    pub fn from_serialized(serialized: SerializedRelRc<N, E>) -> Self {
+    pub fn from_serialized<S: KeyStrategy>(serialized: SerializedRelRc<N, E, S>) -> Self {
         let history = HistoryGraph::from_serialized(serialized.ancestors_graph);
         history.get_node(serialized.id).expect("valid node").clone()
     }
 }
 
-impl<N, E> HistoryGraph<N, E> {
+impl<N: 'static, E: 'static, S: KeyStrategy> HistoryGraph<N, E, S> {
     /// Convert a [`HistoryGraph`] object to its serializable format.
-    pub fn to_serialized(&self) -> SerializedHistoryGraph<N, E>
+    ///
+    /// Ancestors of the nodes that are not in the registry of the graph are
+    /// serialized with the keys that the key strategy would assign to them,
+    /// without registering them. Returns an [`UnassignedKey`] error if the
+    /// key strategy cannot assign a key to one of them.
+    pub fn to_serialized(&self) -> Result<SerializedHistoryGraph<N, E, S>, UnassignedKey>
     where
         N: Clone,
         E: Clone,
     {
         let nodes = BTreeSet::from_iter(self.all_node_ids());
         let mut registry = self.registry().borrow().clone();
-        let ancestors = nodes
+
+        // Collect the ancestors of all nodes, visiting every ancestor once
+        let mut ancestors = FxHashSet::default();
+        let mut stack = nodes
             .iter()
-            .flat_map(|&n| {
-                let node = registry.get(n).expect("invalid node");
-                node.all_ancestors()
-                    .map(|n| registry.get_id_or_insert(n))
-                    .collect_vec()
-            })
-            .collect::<FxHashSet<_>>();
+            .map(|&n| registry.get(n).expect("invalid node"))
+            .collect_vec();
+        while let Some(node) = stack.pop() {
+            let id = registry.try_add_node(&node).ok_or(UnassignedKey)?;
+            if ancestors.insert(id) {
+                stack.extend(node.all_parents().cloned());
+            }
+        }
 
         let mut ser_registry = registry.to_serialized();
-        ser_registry.nodes.retain(|k, _| ancestors.contains(&k));
+        ser_registry.nodes.retain(|k, node| {
+            // Weak edges to nodes that are not serialized become dangling
+            for (parent_id, _) in node.incoming.iter_mut() {
+                if parent_id.is_some_and(|p| !ancestors.contains(&p)) {
+                    *parent_id = None;
+                }
+            }
+            ancestors.contains(&k)
+        });
 
-        SerializedHistoryGraph {
+        Ok(SerializedHistoryGraph {
             nodes,
             registry: ser_registry,
-        }
+        })
     }
 
     /// Convert a serializable representation of a [`HistoryGraph`] object back
     /// to a [`HistoryGraph`] object.
-    pub fn from_serialized(serialized: SerializedHistoryGraph<N, E>) -> Self {
+    pub fn from_serialized(serialized: SerializedHistoryGraph<N, E, S>) -> Self {
         let (registry, mut all_nodes) = Registry::from_serialized(serialized.registry);
         let keep_nodes = FxHashSet::from_iter(serialized.nodes.iter().copied());
-        assert!(keep_nodes.iter().all(|&k| all_nodes.contains_key(k)));
+        assert!(keep_nodes.iter().all(|k| all_nodes.contains_key(k)));
         assert!(keep_nodes.iter().all(|&k| registry.contains_id(k)));
-        all_nodes.retain(|k, _| keep_nodes.contains(&k));
+        all_nodes.retain(|k, _| keep_nodes.contains(k));
         assert!(all_nodes.values().all(|v| registry.contains(v)));
 
-        HistoryGraph::new(all_nodes.into_iter().map(|(_, n)| n), registry)
+        HistoryGraph::new(all_nodes.into_values(), registry)
     }
 }
 
-impl<N, E> From<SerializedRelRc<N, E>> for RelRc<N, E> {
-    fn from(serialized: SerializedRelRc<N, E>) -> Self {
+impl<N: 'static, E: 'static, S: KeyStrategy> From<SerializedRelRc<N, E, S>> for RelRc<N, E> {
+    fn from(serialized: SerializedRelRc<N, E, S>) -> Self {
         Self::from_serialized(serialized)
     }
 }
 
-impl<N: Clone, E: Clone> From<HistoryGraph<N, E>> for SerializedHistoryGraph<N, E> {
-    fn from(value: HistoryGraph<N, E>) -> Self {
+impl<N: Clone + 'static, E: Clone + 'static, S: KeyStrategy> TryFrom<HistoryGraph<N, E, S>>
+    for SerializedHistoryGraph<N, E, S>
+{
+    type Error = UnassignedKey;
+
+    fn try_from(value: HistoryGraph<N, E, S>) -> Result<Self, UnassignedKey> {
         value.to_serialized()
     }
 }
 
-impl<N, E> From<SerializedHistoryGraph<N, E>> for HistoryGraph<N, E> {
-    fn from(value: SerializedHistoryGraph<N, E>) -> Self {
+impl<N: 'static, E: 'static, S: KeyStrategy> From<SerializedHistoryGraph<N, E, S>>
+    for HistoryGraph<N, E, S>
+{
+    fn from(value: SerializedHistoryGraph<N, E, S>) -> Self {
         HistoryGraph::from_serialized(value)
     }
 }
 
-/// Add `node_id` and its ancestors to the registry.
-fn deserialize_nodes<N, E>(
-    mut serialised_nodes: SlotMap<NodeId, SerializedInnerData<N, E>>,
-) -> SecondaryMap<NodeId, RelRc<N, E>> {
-    let mut all_nodes = SecondaryMap::new();
-
-    fn deserialize_node<N, E>(
-        node_id: NodeId,
-        serialised_nodes: &mut SlotMap<NodeId, SerializedInnerData<N, E>>,
-        all_nodes: &mut SecondaryMap<NodeId, RelRc<N, E>>,
-    ) {
-        let node_ser = serialised_nodes.remove(node_id).expect("invalid node_id");
-
-        // Recursively deserialize ancestors
-        for &(parent_id, _) in node_ser.incoming.iter() {
-            if !all_nodes.contains_key(parent_id) {
-                deserialize_node(parent_id, serialised_nodes, all_nodes);
+/// Deserialize all nodes, creating parents before their children.
+///
+/// Uses an explicit stack rather than recursion, so that arbitrarily deep
+/// histories can be deserialized.
+fn deserialize_nodes<N, E, K: Copy + Ord>(
+    mut serialised_nodes: BTreeMap<K, SerializedInnerData<N, E, K>>,
+) -> BTreeMap<K, RelRc<N, E>> {
+    let mut all_nodes: BTreeMap<K, RelRc<N, E>> = BTreeMap::new();
+    let mut stack = Vec::new();
+
+    for root_id in serialised_nodes.keys().copied().collect_vec() {
+        stack.push(root_id);
+        while let Some(&node_id) = stack.last() {
+            if all_nodes.contains_key(&node_id) {
+                stack.pop();
+                continue;
             }
-        }
 
-        // Create incoming edges
-        let incoming = node_ser
-            .incoming
-            .into_iter()
-            .map(|(parent_id, edge_value)| {
-                let parent = all_nodes.get(parent_id).expect("valid dfs order");
-                (parent.clone(), edge_value)
-            });
-
-        let node_deser = RelRc::with_parents(node_ser.value, incoming);
-        all_nodes.insert(node_id, node_deser);
-    }
+            // Deserialize missing ancestors first
+            let node_ser = serialised_nodes.get(&node_id).expect("invalid node_id");
+            let n_stack = stack.len();
+            stack.extend(
+                node_ser
+                    .incoming
+                    .iter()
+                    .filter_map(|&(parent_id, _)| parent_id)
+                    .filter(|parent_id| !all_nodes.contains_key(parent_id)),
+            );
+            if stack.len() > n_stack {
+                continue;
+            }
 
-    while let Some((node_id, _)) = serialised_nodes.iter().next() {
-        deserialize_node(node_id, &mut serialised_nodes, &mut all_nodes);
+            // Create incoming edges
+            stack.pop();
+            let node_ser = serialised_nodes.remove(&node_id).expect("invalid node_id");
+            let incoming = node_ser.incoming.into_iter().enumerate().map(
+                |(index, (parent_id, edge_value))| {
+                    let parent = parent_id.map(|p| all_nodes.get(&p).expect("valid dfs order"));
+                    let source = if node_ser.weak.contains(&index) {
+                        EdgeSource::Weak(parent.map(|p| p.downgrade()).unwrap_or_default())
+                    } else {
+                        EdgeSource::Strong(parent.expect("strong edge has a source").clone())
+                    };
+                    (source, edge_value)
+                },
+            );
+
+            // Hashes are restored rather than recomputed, as dangling edges
+            // may not have been dangling when the node was hashed.
+            let node_deser =
+                RelRc::with_sources_and_hash(node_ser.value, incoming, |_, _| node_ser.hash_id);
+            all_nodes.insert(node_id, node_deser);
+        }
     }
 
     all_nodes
@@ -252,6 +377,8 @@ fn deserialize_nodes<N, E>(
 #[cfg(feature = "serde")]
 mod tests {
     use super::*;
+    use crate::hash_id::Fnv1a;
+    use crate::registry::SuppliedKeys;
     use crate::{HistoryGraph, RelRc};
     use insta::assert_yaml_snapshot;
 
@@ -259,7 +386,7 @@ mod tests {
     fn test_relrc_serialization() {
         // Create a simple RelRc node
         let node = RelRc::<_, ()>::new(42);
-        let serialized = node.to_serialized(Registry::new());
+        let serialized = node.to_serialized(Registry::new()).unwrap();
 
         assert_yaml_snapshot!(serialized);
 
@@ -276,7 +403,7 @@ mod tests {
         let child = RelRc::with_parents("child", [(parent, "edge_to_child")]);
         let grandchild = RelRc::with_parents("grandchild", [(child, "edge_to_grandchild")]);
 
-        let serialized = grandchild.to_serialized(Registry::new());
+        let serialized = grandchild.to_serialized(Registry::new()).unwrap();
 
         assert_yaml_snapshot!(serialized);
 
@@ -305,7 +432,7 @@ mod tests {
 
         let registry = Registry::from_iter([&parent1, &parent2, &sibling, &child]);
 
-        let serialized = child.to_serialized(registry);
+        let serialized = child.to_serialized(registry).unwrap();
 
         assert_eq!(serialized.ancestors_graph.nodes.len(), 3);
 
@@ -333,37 +460,105 @@ mod tests {
         );
 
         let graph = HistoryGraph::from_nodes([child1.clone(), child2.clone()]);
-        let serialized = SerializedHistoryGraph::from(graph.clone());
+        let serialized = SerializedHistoryGraph::try_from(graph.clone()).unwrap();
 
         // Test JSON serialization
         assert_yaml_snapshot!("two_parents_two_children", serialized);
 
         let deser = HistoryGraph::from_serialized(serialized);
-
-        dbg!(&deser
-            .all_node_ids()
-            .map(|n| deser.get_node(n).unwrap())
-            .collect_vec());
-        dbg!(&deser.registry().borrow());
-        assert_eq!(
-            deser.all_node_ids().collect::<BTreeSet<_>>(),
-            BTreeSet::from_iter([
-                deser.registry().borrow().get_id(&child1).unwrap(),
-                deser.registry().borrow().get_id(&child2).unwrap(),
-            ])
-        );
+        let values = |graph: &HistoryGraph<&'static str, &'static str>| {
+            graph
+                .all_node_ids()
+                .map(|n| *graph.get_node(n).unwrap().value())
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(values(&deser), BTreeSet::from_iter(["child1", "child2"]));
         assert_eq!(deser.registry().borrow().len(), 4);
 
         let graph = HistoryGraph::from_nodes([child1.clone()]);
-        let serialized = SerializedHistoryGraph::from(graph.clone());
+        let serialized = SerializedHistoryGraph::try_from(graph.clone()).unwrap();
 
         assert_yaml_snapshot!("one_parent_one_child", serialized);
 
         let deser = HistoryGraph::from_serialized(serialized);
+        assert_eq!(values(&deser), BTreeSet::from_iter(["child1"]));
+        assert_eq!(deser.registry().borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_hash_id_serialization() {
+        let root = RelRc::new_hashed::<Fnv1a>("root".to_string());
+        let child = RelRc::with_parents_hashed::<Fnv1a>("child".to_string(), [(root, 1)]).unwrap();
+
+        let json = serde_json::to_string(&child.to_serialized(Registry::new()).unwrap()).unwrap();
+        let serialized: SerializedRelRc<String, usize> = serde_json::from_str(&json).unwrap();
+        let deser = RelRc::from_serialized(serialized);
+        assert_eq!(deser.hash_id(), child.hash_id());
         assert_eq!(
-            deser.all_node_ids().collect::<BTreeSet<_>>(),
-            BTreeSet::from_iter([deser.registry().borrow().get_id(&child1).unwrap(),])
+            deser.parent(0).unwrap().hash_id(),
+            child.parent(0).unwrap().hash_id()
         );
-        assert_eq!(deser.registry().borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_weak_edges_serialization() {
+        let strong = RelRc::new("strong");
+        let weak = RelRc::new("weak");
+        let child = RelRc::with_sources(
+            "child",
+            [
+                (EdgeSource::Weak(weak.downgrade()), "weak_edge"),
+                (EdgeSource::Strong(strong.clone()), "strong_edge"),
+            ],
+        );
+
+        // The weak parent is only serialized if it is in the graph
+        let graph = HistoryGraph::from_nodes([strong.clone(), weak.clone(), child.clone()]);
+        let deser = HistoryGraph::from_serialized(graph.to_serialized().unwrap());
+        let child_id = deser
+            .all_node_ids()
+            .find(|&n| deser.get_node(n).unwrap().value() == &"child")
+            .unwrap();
+        assert_eq!(deser.parents(child_id).count(), 2);
+        assert_eq!(deser.dangling_edges(child_id).count(), 0);
+
+        drop(graph);
+        let graph = HistoryGraph::from_nodes([child.clone()]);
+        let deser = HistoryGraph::from_serialized(graph.to_serialized().unwrap());
+        let child_id = deser
+            .all_node_ids()
+            .find(|&n| deser.get_node(n).unwrap().value() == &"child")
+            .unwrap();
+        let deser_child = deser.get_node(child_id).unwrap();
+        assert!(deser_child.incoming(0).unwrap().is_dangling());
+        assert_eq!(
+            deser_child.all_parents().map(|n| n.value()).collect_vec(),
+            vec![&"strong"]
+        );
+    }
+
+    #[test]
+    fn test_supplied_keys_serialization() {
+        let registry = Rc::new(RefCell::new(Registry::with_keys(
+            SuppliedKeys::<&str>::default(),
+        )));
+        let parent = RelRc::new("parent");
+        let child = RelRc::with_parents("child", [(parent.clone(), ())]);
+        let mut graph = HistoryGraph::with_registry(registry.clone());
+        graph.try_insert_node_with_key(child.clone(), "c").unwrap();
+
+        // The parent is not in the registry and cannot be assigned a key
+        assert_eq!(graph.to_serialized().unwrap_err(), UnassignedKey);
+        assert_eq!(
+            child.to_serialized(registry.clone()).unwrap_err(),
+            UnassignedKey
+        );
+
+        graph.try_insert_node_with_key(parent, "p").unwrap();
+        let deser = HistoryGraph::from_serialized(graph.to_serialized().unwrap());
+        assert_eq!(deser.all_node_ids().collect_vec(), ["c", "p"]);
+        assert_eq!(deser.parents("c").collect_vec(), ["p"]);
+        let ser_child = child.to_serialized(registry).unwrap();
+        assert_eq!(ser_child.id, "c");
     }
 }
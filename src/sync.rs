@@ -0,0 +1,459 @@
+//! Thread-safe reference-counted pointers.
+//!
+//! This module mirrors the [`RelRc`](crate::RelRc) API with atomically
+//! reference-counted pointers: [`RelArc`] is to [`RelRc`](crate::RelRc) what
+//! [`Arc`] is to [`Rc`](std::rc::Rc). Outgoing edge lists and registry slots
+//! are protected by locks, so children of a shared node can be created
+//! concurrently from several threads.
+
+pub mod edge;
+pub mod registry;
+
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, Weak};
+
+use derive_more::From;
+use derive_where::derive_where;
+
+use crate::pointer::Shared;
+use crate::traversal::AncestorTraversal;
+use crate::NodeId;
+pub use edge::{Edge, InnerEdgeData, WeakEdge};
+pub use registry::Registry;
+
+/// A thread-safe reference-counted pointer, optionally with relationships
+/// to other [`RelArc`] objects.
+///
+/// The thread-safe counterpart of [`RelRc`](crate::RelRc). A new [`RelArc`]
+/// object is created with either
+///  - [`RelArc::new`]: behaves identically to [`Arc::new`], or
+///  - [`RelArc::with_parents`]: creates a new [`RelArc`] object, with a list
+///    of parent [`RelArc`] objects.
+///
+/// A [`RelArc`] object will remain in memory for as long as there is at least
+/// one strong reference to it or to one of its descendants. Just like
+/// [`RelRc`](crate::RelRc), ancestors are released iteratively when the last
+/// reference is dropped.
+#[derive(Debug)]
+#[derive_where(Clone)]
+pub struct RelArc<N, E>(ManuallyDrop<Arc<InnerData<N, E>>>);
+
+impl<N, E> From<Arc<InnerData<N, E>>> for RelArc<N, E> {
+    fn from(inner: Arc<InnerData<N, E>>) -> Self {
+        Self(ManuallyDrop::new(inner))
+    }
+}
+
+impl<N, E> RelArc<N, E> {
+    /// Create a new [`RelArc<N, E>`] with no parents.
+    pub fn new(value: N) -> Self {
+        let inner = Arc::new(InnerData::new(value));
+        inner.into()
+    }
+
+    /// Create a new [`RelArc<N, E>`] with the given list of parent objects.
+    ///
+    /// The parents must be given by an object [`RelArc<N, E>`] and its
+    /// corresponding edge value. The order of the parents is guaranteed to
+    /// never change.
+    pub fn with_parents(value: N, parents: impl IntoIterator<Item = (RelArc<N, E>, E)>) -> Self {
+        let inner = Arc::new_cyclic(|weak_node| {
+            let weak_node: RelArcWeak<N, E> = weak_node.clone().into();
+            let incoming = parents
+                .into_iter()
+                .map(|(parent, edge_value)| {
+                    InnerEdgeData::new(edge_value, parent, weak_node.clone())
+                })
+                .collect();
+            InnerData::with_incoming(value, incoming)
+        });
+        let node = Self::from(inner);
+        register_outgoing_edges(&node.incoming);
+        node
+    }
+}
+
+impl<N, E> RelArc<N, E> {
+    /// Get a raw pointer to the underlying data.
+    ///
+    /// This is a low-level function that returns a raw pointer to the
+    /// underlying data. The pointer is valid as long as at least one reference
+    /// to the data exists.
+    pub fn as_ptr(&self) -> *const InnerData<N, E> {
+        Arc::as_ptr(&self.0)
+    }
+
+    /// Check if two pointers point to the same underlying data by comparing
+    /// their raw pointers.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Downgrade the node to a weak reference.
+    pub fn downgrade(&self) -> RelArcWeak<N, E> {
+        RelArcWeak(Arc::downgrade(&self.0))
+    }
+
+    /// Register this node in the given registry and return its ID.
+    ///
+    /// A node can only be registered in one registry at a time. If the node is
+    /// already registered in a different registry that is still alive, this
+    /// will return `None`.
+    ///
+    /// The node ID in the registry will be freed after the last reference to
+    /// `self` is dropped, see [`Registry`].
+    #[must_use]
+    pub fn try_register_in(&self, registry: &Arc<Mutex<Registry<N, E>>>) -> Option<NodeId> {
+        let mut slot = self.0.registry.lock().expect("registry slot poisoned");
+        let weak = Arc::downgrade(registry);
+        match slot.as_ref() {
+            Some(existing) if existing.ptr_eq(&weak) => {}
+            Some(existing) if existing.strong_count() > 0 => return None,
+            // The node is not registered, or its registry has been dropped
+            _ => {
+                slot.replace(weak);
+            }
+        }
+        let id = registry.lock().expect("registry poisoned").add_node(self);
+        Some(id)
+    }
+
+    /// Get the registry that this node is registered in, if there is one.
+    pub fn registry(&self) -> Option<Arc<Mutex<Registry<N, E>>>> {
+        self.0
+            .registry
+            .lock()
+            .expect("registry slot poisoned")
+            .as_ref()
+            .and_then(|weak| weak.upgrade())
+    }
+
+    /// Create a traversal over the ancestors of the object, including self.
+    ///
+    /// See [`RelRc::traverse_ancestors`](crate::RelRc::traverse_ancestors).
+    pub fn traverse_ancestors(&self) -> AncestorTraversal<'_, N, E, Shared> {
+        AncestorTraversal::new(self)
+    }
+
+    /// Iterate over all ancestors of the object, including self.
+    ///
+    /// Ancestors are visited in breadth-first order. Use
+    /// [`RelArc::traverse_ancestors`] for other traversal orders.
+    pub fn all_ancestors(&self) -> impl Iterator<Item = &RelArc<N, E>> + '_ {
+        self.traverse_ancestors().into_iter().map(|(node, _)| node)
+    }
+}
+
+/// A weak reference to a [`RelArc`] object.
+///
+/// Upgrades to [`RelArc`] if the reference is valid.
+#[derive(Debug, From)]
+#[derive_where(Clone)]
+pub struct RelArcWeak<N, E>(Weak<InnerData<N, E>>);
+
+impl<N, E> RelArcWeak<N, E> {
+    /// Upgrades to a [`RelArc`] if the reference is still valid.
+    pub fn upgrade(&self) -> Option<RelArc<N, E>> {
+        self.0.upgrade().map(RelArc::from)
+    }
+
+    /// Check if two weak references point to the same underlying data
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Weak::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Get a raw pointer to the underlying data.
+    pub fn as_ptr(&self) -> *const InnerData<N, E> {
+        Weak::as_ptr(&self.0)
+    }
+}
+
+/// A weak reference to a thread-safe [`Registry`] object.
+pub type WeakRegistry<N, E> = Weak<Mutex<Registry<N, E>>>;
+
+/// Data within a [`RelArc`] object.
+///
+/// Keeps track of its incident edges. Sole owner of the incoming edges, i.e.
+/// the edges will exist if and only if the node exists. References to outgoing
+/// edges are weak references, thus they may get deleted if all downstream nodes
+/// have been deleted.
+#[derive(Debug)]
+pub struct InnerData<N, E> {
+    /// The value of the node.
+    value: N,
+    /// The incoming edges to the object.
+    ///
+    /// The ordering and position of the incoming edges is immutable.
+    incoming: Vec<InnerEdgeData<N, E>>,
+    /// The outgoing edges from the object (weak references).
+    ///
+    /// The order and position of the outgoing edges may change at any time, as
+    /// the edges may get deleted.
+    outgoing: RwLock<Vec<WeakEdge<N, E>>>,
+    /// The registry that tracks this node, if there is one.
+    registry: Mutex<Option<WeakRegistry<N, E>>>,
+}
+
+impl<N, E> Deref for RelArc<N, E> {
+    type Target = InnerData<N, E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<N: Default, E> Default for RelArc<N, E> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<N, E> InnerData<N, E> {
+    fn new(value: N) -> Self {
+        Self::with_incoming(value, Vec::new())
+    }
+
+    fn with_incoming(value: N, incoming: Vec<InnerEdgeData<N, E>>) -> Self {
+        Self {
+            value,
+            incoming,
+            outgoing: RwLock::new(Vec::new()),
+            registry: Mutex::new(None),
+        }
+    }
+
+    /// The i-th incoming edge to the node.
+    pub fn incoming(&self, index: usize) -> Option<&InnerEdgeData<N, E>> {
+        self.incoming.get(index)
+    }
+
+    /// The i-th incoming edge to the node as a weak reference.
+    pub fn incoming_weak(&self, index: usize) -> Option<WeakEdge<N, E>> {
+        self.incoming
+            .get(index)
+            .map(|e| WeakEdge::new(index, e.target.clone()))
+    }
+
+    /// The i-th parent of the object.
+    pub fn parent(&self, index: usize) -> Option<&RelArc<N, E>> {
+        self.incoming.get(index).map(|e| e.source())
+    }
+
+    /// The value of the object, also obtainable with [`Deref`].
+    pub fn value(&self) -> &N {
+        &self.value
+    }
+
+    /// All incoming edges as a slice.
+    pub fn all_incoming(&self) -> &[InnerEdgeData<N, E>] {
+        &self.incoming
+    }
+
+    /// All incoming edges as weak references.
+    pub fn all_incoming_weak(&self) -> impl ExactSizeIterator<Item = WeakEdge<N, E>> + '_ {
+        self.all_incoming()
+            .iter()
+            .enumerate()
+            .map(|(i, e)| WeakEdge::new(i, e.target.clone()))
+    }
+
+    /// All outgoing edges as weak references.
+    ///
+    /// This takes a read lock on the outgoing edges, meaning that no new
+    /// children can be created for as long as the guard is in scope.
+    fn all_outgoing_weak_ref(&self) -> RwLockReadGuard<'_, Vec<WeakEdge<N, E>>> {
+        self.outgoing.read().expect("outgoing edges poisoned")
+    }
+
+    /// All outgoing edges as weak references.
+    pub fn all_outgoing_weak(&self) -> Vec<WeakEdge<N, E>> {
+        self.all_outgoing_weak_ref().to_vec()
+    }
+
+    /// Iterate over all parents of the object.
+    pub fn all_parents(&self) -> impl ExactSizeIterator<Item = &RelArc<N, E>> {
+        self.all_incoming().iter().map(|e| e.source())
+    }
+
+    /// The number of incoming edges.
+    pub fn n_incoming(&self) -> usize {
+        self.incoming.len()
+    }
+
+    /// Iterate over all outgoing edges.
+    ///
+    /// The edges are weakly referenced, so they may get deleted if all
+    /// downstream nodes have been deleted.
+    ///
+    /// This upgrades all outgoing edges, removes references to edges that have
+    /// been deleted, and returns the remaining edges in a new vector. This is
+    /// not done lazily to limit the time the outgoing lock is held.
+    pub fn all_outgoing(&self) -> Vec<Edge<N, E>> {
+        let mut outgoing = self.outgoing.write().expect("outgoing edges poisoned");
+        let mut edges = Vec::with_capacity(outgoing.len());
+        outgoing.retain(|e| {
+            if let Some(edge) = e.upgrade() {
+                edges.push(edge);
+                true
+            } else {
+                false
+            }
+        });
+        edges
+    }
+
+    /// Iterate over all children of the object.
+    ///
+    /// The children are the objects that have an incoming edge from the object.
+    pub fn all_children(&self) -> impl ExactSizeIterator<Item = RelArc<N, E>> {
+        self.all_outgoing().into_iter().map(|e| e.into_target())
+    }
+
+    /// The number of outgoing edges.
+    pub fn n_outgoing(&self) -> usize {
+        self.all_outgoing().len()
+    }
+}
+
+impl<N, E> RelArc<N, E> {
+    /// Consume the handle and return the underlying `Arc`, without running
+    /// [`Drop`] for [`RelArc`].
+    fn into_arc(self) -> Arc<InnerData<N, E>> {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so `this.0` is taken exactly once.
+        unsafe { ManuallyDrop::take(&mut this.0) }
+    }
+}
+
+impl<N, E> Drop for RelArc<N, E> {
+    fn drop(&mut self) {
+        // SAFETY: `self.0` is not accessed again after being taken.
+        let inner = unsafe { ManuallyDrop::take(&mut self.0) };
+        release(inner);
+    }
+}
+
+/// Drop a strong reference to a node.
+///
+/// If it is the last strong reference, the incoming edges of the node are
+/// dropped iteratively. [`Arc::into_inner`] guarantees that exactly one thread
+/// releases the node, even if several threads drop their references
+/// concurrently. The registry of the node is not locked: it ignores the dead
+/// reference to the node, see [`Registry`].
+fn release<N, E>(inner: Arc<InnerData<N, E>>) {
+    let mut worklist = vec![inner];
+    while let Some(inner) = worklist.pop() {
+        let Some(data) = Arc::into_inner(inner) else {
+            continue;
+        };
+        worklist.extend(data.incoming.into_iter().map(|e| e.source.into_arc()));
+    }
+}
+
+fn register_outgoing_edges<N, E>(incoming: &[InnerEdgeData<N, E>]) {
+    for (i, edge) in incoming.iter().enumerate() {
+        edge.source()
+            .outgoing
+            .write()
+            .expect("outgoing edges poisoned")
+            .push(edge.downgrade(i));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<RelArc<String, usize>>();
+        assert_send_sync::<RelArcWeak<String, usize>>();
+        assert_send_sync::<Edge<String, usize>>();
+        assert_send_sync::<Arc<Mutex<Registry<String, usize>>>>();
+    }
+
+    #[test]
+    fn test_concurrent_children() {
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        let root = RelArc::new(0);
+        let root_id = root.try_register_in(&registry).unwrap();
+
+        let handles = (0..8)
+            .map(|t| {
+                let root = root.clone();
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    (0..100)
+                        .map(|i| {
+                            let child = RelArc::with_parents(t * 100 + i, [(root.clone(), t)]);
+                            child.try_register_in(&registry).unwrap();
+                            child
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let children: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+
+        assert_eq!(root.n_outgoing(), 800);
+        assert_eq!(registry.lock().unwrap().len(), 801);
+        assert!(children
+            .iter()
+            .all(|c| c.all_ancestors().any(|a| a.ptr_eq(&root))));
+
+        drop(children);
+        assert_eq!(root.n_outgoing(), 0);
+        assert_eq!(registry.lock().unwrap().free_node_ids(), 1);
+        assert!(registry.lock().unwrap().get(root_id).is_some());
+    }
+
+    #[test]
+    fn test_drop_while_registry_locked() {
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        let root = RelArc::<_, ()>::new("root");
+        let child = RelArc::with_parents("child", [(root.clone(), ())]);
+        let root_id = root.try_register_in(&registry).unwrap();
+        let child_id = child.try_register_in(&registry).unwrap();
+        drop(root);
+
+        // Releasing the last handles to both nodes must not lock the registry
+        let guard = registry.lock().unwrap();
+        assert!(guard.get(root_id).is_some());
+        drop(child);
+        assert!(guard.get(root_id).is_none() && !guard.contains_id(child_id));
+        assert_eq!(guard.iter().count(), 0);
+        assert!(guard.is_empty());
+        drop(guard);
+
+        // The dead references are removed once the registry is unlocked
+        let mut registry = registry.lock().unwrap();
+        let other = RelArc::new("other");
+        let other_id = registry.add_node(&other);
+        assert_eq!(registry.free_node_ids(), 1);
+        assert!(registry.get(other_id).is_some());
+    }
+
+    #[test]
+    fn test_register_after_registry_dropped() {
+        let node = RelArc::<_, ()>::new("node");
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        node.try_register_in(&registry).unwrap();
+        assert!(node
+            .try_register_in(&Arc::new(Mutex::new(Registry::new())))
+            .is_none());
+        drop(registry);
+
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        let id = node.try_register_in(&registry).unwrap();
+        assert!(registry.lock().unwrap().get(id).unwrap().ptr_eq(&node));
+        assert!(Arc::ptr_eq(&node.registry().unwrap(), &registry));
+    }
+}
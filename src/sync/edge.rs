@@ -0,0 +1,31 @@
+//! Parent-child relationships between [`RelArc`] objects.
+//!
+//! The edges are the same types as the edges between [`RelRc`](crate::RelRc)
+//! objects, see [`crate::edge`], with the [`Shared`] pointer kind. Edges
+//! between [`RelArc`] objects always keep their source alive.
+
+use crate::pointer::Shared;
+
+use super::RelArc;
+
+/// A parent-child relationship between two [`RelArc`] objects.
+///
+/// See [`crate::edge::InnerEdgeData`].
+pub type InnerEdgeData<N, E> = crate::edge::InnerEdgeData<N, E, Shared>;
+
+/// Weak reference to an edge between [`RelArc`] objects.
+///
+/// See [`crate::edge::WeakEdge`].
+pub type WeakEdge<N, E> = crate::edge::WeakEdge<N, E, Shared>;
+
+/// Strong reference to an edge between [`RelArc`] objects.
+///
+/// See [`crate::edge::Edge`].
+pub type Edge<N, E> = crate::edge::Edge<N, E, Shared>;
+
+impl<N, E> InnerEdgeData<N, E> {
+    /// The source node of the edge.
+    pub fn source(&self) -> &RelArc<N, E> {
+        &self.source
+    }
+}
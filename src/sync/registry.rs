@@ -0,0 +1,148 @@
+//! Node registry for assigning unique IDs to [`RelArc`] nodes.
+
+use derive_where::derive_where;
+use fxhash::FxHashMap;
+use itertools::Itertools;
+use slotmap_fork_lmondada::SlotMap;
+use std::sync::{Arc, Mutex};
+
+use super::{RelArc, RelArcWeak};
+use crate::NodeId;
+
+/// Thread-safe registry for assigning unique IDs to [`RelArc`] nodes.
+///
+/// The counterpart of [`crate::Registry`] for [`RelArc`] nodes. It is meant
+/// to be shared between threads as an `Arc<Mutex<Registry<N, E>>>`.
+///
+/// Dropping a node never locks its registry, so that the last reference to a
+/// node may be dropped while the registry is locked. Instead, the registry
+/// keeps a dead reference to the node, which is ignored by all lookups. Dead
+/// references are removed when the registry would otherwise grow to add a
+/// node, or explicitly with [`Registry::free_node_ids`].
+#[derive(Debug)]
+#[derive_where(Clone, Default)]
+pub struct Registry<N, E> {
+    /// Map from NodeId to weak references to nodes
+    nodes: SlotMap<NodeId, RelArcWeak<N, E>>,
+    /// Inverse map from node address to NodeId for fast lookups.
+    ///
+    /// Addresses are stored as `usize` rather than raw pointers, so that the
+    /// registry is `Send`. The address of a dropped node is not reused until
+    /// its dead reference is removed, as the reference keeps the allocation
+    /// alive.
+    ptr_to_id: FxHashMap<usize, NodeId>,
+}
+
+impl<N, E> Registry<N, E> {
+    /// Create a new empty node registry.
+    pub fn new() -> Self {
+        Self {
+            nodes: SlotMap::with_key(),
+            ptr_to_id: FxHashMap::default(),
+        }
+    }
+
+    /// Add a [`RelArc`] node to the registry and return its unique ID.
+    ///
+    /// If the node is already in the registry, return the existing ID.
+    ///
+    /// Prefer registering the node using [`RelArc::try_register_in`], as this
+    /// will also free the node ID when the node goes out of scope.
+    pub fn add_node(&mut self, node: &RelArc<N, E>) -> NodeId {
+        if let Some(existing_id) = self.get_id(node) {
+            return existing_id;
+        }
+
+        if self.nodes.len() == self.nodes.capacity() {
+            self.free_node_ids();
+        }
+        let id = self.nodes.insert(node.downgrade());
+        self.ptr_to_id.insert(node.as_ptr() as usize, id);
+        id
+    }
+
+    /// Get the NodeId for a [`RelArc`] node if it's registered.
+    pub fn get_id(&self, node: &RelArc<N, E>) -> Option<NodeId> {
+        self.ptr_to_id.get(&(node.as_ptr() as usize)).copied()
+    }
+
+    /// Check if a [`RelArc`] is contained in the registry.
+    pub fn contains(&self, node: &RelArc<N, E>) -> bool {
+        self.get_id(node).is_some()
+    }
+
+    /// Get the [`RelArc`] node associated with the given ID.
+    ///
+    /// If the weak reference cannot be upgraded, none is returned.
+    pub fn get(&self, id: NodeId) -> Option<RelArc<N, E>> {
+        self.nodes.get(id)?.upgrade()
+    }
+
+    /// Check if a node ID is registered.
+    pub fn contains_id(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Remove all dead references and return the number of live nodes.
+    ///
+    /// This is automatically done when the registry would otherwise grow, but
+    /// can be called explicitly for bulk cleanup.
+    pub fn free_node_ids(&mut self) -> usize {
+        let dead_ids = self
+            .nodes
+            .iter()
+            .filter(|(_, weak_ref)| weak_ref.upgrade().is_none())
+            .map(|(id, _)| id)
+            .collect_vec();
+        for id in dead_ids {
+            self.remove(id);
+        }
+        self.nodes.len()
+    }
+
+    /// Iterate over all live nodes in the registry.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, RelArc<N, E>)> + '_ {
+        self.nodes
+            .iter()
+            .filter_map(|(id, weak_ref)| Some((id, weak_ref.upgrade()?)))
+    }
+
+    /// Get the number of live registered nodes.
+    ///
+    /// The IDs of dead nodes are not counted, even if they have not been freed
+    /// yet, see [`Self::free_node_ids`].
+    pub fn len(&self) -> usize {
+        self.nodes
+            .values()
+            .filter(|weak_ref| weak_ref.0.strong_count() > 0)
+            .count()
+    }
+
+    /// Check if the registry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove a node from the registry.
+    pub fn remove(&mut self, id: NodeId) {
+        if let Some(weak_ref) = self.nodes.remove(id) {
+            self.ptr_to_id.remove(&(weak_ref.as_ptr() as usize));
+        }
+    }
+}
+
+impl<'r, N, E> FromIterator<&'r RelArc<N, E>> for Registry<N, E> {
+    fn from_iter<T: IntoIterator<Item = &'r RelArc<N, E>>>(iter: T) -> Self {
+        let mut registry = Self::new();
+        for node in iter {
+            registry.add_node(node);
+        }
+        registry
+    }
+}
+
+impl<N, E> From<Registry<N, E>> for Arc<Mutex<Registry<N, E>>> {
+    fn from(registry: Registry<N, E>) -> Self {
+        Arc::new(Mutex::new(registry))
+    }
+}
@@ -0,0 +1,337 @@
+//! Configurable traversals over the ancestors of a [`RelRc`] object.
+//!
+//! Use [`RelRc::traverse_ancestors`] to create an [`AncestorTraversal`], then
+//! configure it and iterate over it. The ancestors of thread-safe
+//! [`RelArc`](crate::RelArc) objects are traversed in the same way, see
+//! [`RelArc::traverse_ancestors`](crate::RelArc::traverse_ancestors).
+
+use std::collections::hash_map::Entry;
+use std::collections::VecDeque;
+use std::iter;
+
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::edge::InnerEdgeData;
+use crate::pointer::{Local, PointerKind};
+use crate::RelRc;
+
+/// The order in which an [`AncestorTraversal`] visits the ancestors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TraversalOrder {
+    /// Visit nodes by increasing depth.
+    #[default]
+    BreadthFirst,
+    /// Visit nodes in depth-first pre-order, following parents in the order
+    /// of the incoming edges.
+    DepthFirst,
+    /// Visit every node after all of its traversed parents, i.e. roots first
+    /// and the starting node last.
+    Topological,
+}
+
+type EdgeFilter<'a, N, E, P> = Box<dyn Fn(&InnerEdgeData<N, E, P>) -> bool + 'a>;
+
+/// A configurable traversal over the ancestors of a [`RelRc`] object,
+/// including the object itself.
+///
+/// Only strong edges are traversed: the sources of weak edges are not
+/// considered ancestors. Use [`InnerData::all_dangling`] to find the weak
+/// edges of a visited node whose source has been dropped.
+///
+/// Created with [`RelRc::traverse_ancestors`]. Iterating over the traversal
+/// yields every ancestor exactly once, along with its depth.
+///
+/// The depth of a node is its distance from the starting node (at depth 0).
+/// For [`TraversalOrder::BreadthFirst`] and [`TraversalOrder::Topological`]
+/// this is the length of the shortest path of traversed edges. For
+/// [`TraversalOrder::DepthFirst`] it is the length of the path along which the
+/// node was first reached.
+pub struct AncestorTraversal<'a, N, E, P: PointerKind = Local> {
+    start: &'a P::Node<N, E>,
+    order: TraversalOrder,
+    max_depth: Option<usize>,
+    edge_filter: Option<EdgeFilter<'a, N, E, P>>,
+}
+
+impl<N, E> RelRc<N, E> {
+    /// Create a traversal over the ancestors of the object, including self.
+    ///
+    /// By default, ancestors are visited in breadth-first order, without depth
+    /// limit, following all incoming edges.
+    pub fn traverse_ancestors(&self) -> AncestorTraversal<'_, N, E> {
+        AncestorTraversal::new(self)
+    }
+}
+
+impl<'a, N: 'a, E: 'a, P: PointerKind> AncestorTraversal<'a, N, E, P> {
+    /// A breadth-first traversal from `start`, without depth limit, following
+    /// all incoming edges.
+    pub(crate) fn new(start: &'a P::Node<N, E>) -> Self {
+        Self {
+            start,
+            order: TraversalOrder::default(),
+            max_depth: None,
+            edge_filter: None,
+        }
+    }
+
+    /// Set the order in which ancestors are visited.
+    pub fn order(mut self, order: TraversalOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Visit ancestors in breadth-first order.
+    pub fn breadth_first(self) -> Self {
+        self.order(TraversalOrder::BreadthFirst)
+    }
+
+    /// Visit ancestors in depth-first order.
+    pub fn depth_first(self) -> Self {
+        self.order(TraversalOrder::DepthFirst)
+    }
+
+    /// Visit ancestors in topological order, parents before children.
+    pub fn topological(self) -> Self {
+        self.order(TraversalOrder::Topological)
+    }
+
+    /// Only visit ancestors up to the given depth.
+    ///
+    /// A maximum depth of 0 only visits the starting node.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Only follow the incoming edges for which `filter` returns `true`.
+    ///
+    /// Ancestors that can only be reached through filtered out edges are not
+    /// visited.
+    pub fn filter_edges(mut self, filter: impl Fn(&InnerEdgeData<N, E, P>) -> bool + 'a) -> Self {
+        self.edge_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Whether the parents of a node at `depth` should be visited.
+    fn expand(&self, depth: usize) -> bool {
+        self.max_depth.map_or(true, |max_depth| depth < max_depth)
+    }
+
+    /// The parents of `node` reached through followed edges.
+    ///
+    /// Weak edges are never followed.
+    fn parents(&self, node: &'a P::Node<N, E>) -> impl Iterator<Item = &'a P::Node<N, E>> + '_ {
+        P::incoming(node)
+            .iter()
+            .filter(|&e| self.edge_filter.as_ref().map_or(true, |f| f(e)))
+            .filter_map(|e| P::strong_source(&e.source))
+    }
+
+    /// Visit the next node in breadth-first order.
+    ///
+    /// Nodes are marked as seen when they are queued, so that they are visited
+    /// at their shortest depth.
+    fn next_breadth_first(
+        &self,
+        queue: &mut VecDeque<(&'a P::Node<N, E>, usize)>,
+        seen: &mut FxHashSet<*const ()>,
+    ) -> Option<(&'a P::Node<N, E>, usize)> {
+        let (node, depth) = queue.pop_front()?;
+        if self.expand(depth) {
+            for parent in self.parents(node) {
+                if seen.insert(P::node_addr(parent)) {
+                    queue.push_back((parent, depth + 1));
+                }
+            }
+        }
+        Some((node, depth))
+    }
+
+    /// All traversed nodes in topological order, with their shortest depth.
+    fn topological_depths(&self) -> Vec<(&'a P::Node<N, E>, usize)> {
+        let mut queue = VecDeque::from([(self.start, 0)]);
+        let mut seen = FxHashSet::from_iter([P::node_addr(self.start)]);
+        let depths: FxHashMap<*const (), usize> =
+            iter::from_fn(|| self.next_breadth_first(&mut queue, &mut seen))
+                .map(|(node, depth)| (P::node_addr(node), depth))
+                .collect();
+
+        // DFS post-order over the traversed nodes: parents come first
+        let mut post_order = Vec::with_capacity(depths.len());
+        let mut seen = FxHashSet::from_iter([P::node_addr(self.start)]);
+        let mut stack = vec![(self.start, self.parents(self.start))];
+        while let Some((_, parents)) = stack.last_mut() {
+            let next_parent = parents.find(|&p| depths.contains_key(&P::node_addr(p)));
+            if let Some(parent) = next_parent {
+                if seen.insert(P::node_addr(parent)) {
+                    stack.push((parent, self.parents(parent)));
+                }
+            } else {
+                let (node, _) = stack.pop().expect("non-empty stack");
+                post_order.push((node, depths[&P::node_addr(node)]));
+            }
+        }
+        post_order
+    }
+}
+
+impl<'a, N: 'a, E: 'a, P: PointerKind> IntoIterator for AncestorTraversal<'a, N, E, P> {
+    type Item = (&'a P::Node<N, E>, usize);
+    type IntoIter = AncestorIter<'a, N, E, P>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let state = match self.order {
+            TraversalOrder::BreadthFirst => IterState::BreadthFirst {
+                queue: VecDeque::from([(self.start, 0)]),
+                seen: FxHashSet::from_iter([P::node_addr(self.start)]),
+            },
+            TraversalOrder::Topological => {
+                IterState::Topological(self.topological_depths().into_iter())
+            }
+            TraversalOrder::DepthFirst => IterState::DepthFirst {
+                stack: vec![(self.start, 0)],
+                depths: FxHashMap::default(),
+            },
+        };
+        AncestorIter {
+            traversal: self,
+            state,
+        }
+    }
+}
+
+/// Iterator over the ancestors of a [`RelRc`] object and their depths.
+///
+/// Created by iterating over an [`AncestorTraversal`].
+pub struct AncestorIter<'a, N, E, P: PointerKind = Local> {
+    traversal: AncestorTraversal<'a, N, E, P>,
+    state: IterState<'a, N, E, P>,
+}
+
+enum IterState<'a, N, E, P: PointerKind> {
+    BreadthFirst {
+        queue: VecDeque<(&'a P::Node<N, E>, usize)>,
+        seen: FxHashSet<*const ()>,
+    },
+    Topological(std::vec::IntoIter<(&'a P::Node<N, E>, usize)>),
+    DepthFirst {
+        stack: Vec<(&'a P::Node<N, E>, usize)>,
+        /// The smallest depth at which every seen node was reached.
+        depths: FxHashMap<*const (), usize>,
+    },
+}
+
+impl<'a, N: 'a, E: 'a, P: PointerKind> Iterator for AncestorIter<'a, N, E, P> {
+    type Item = (&'a P::Node<N, E>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.state {
+            IterState::BreadthFirst { queue, seen } => {
+                self.traversal.next_breadth_first(queue, seen)
+            }
+            IterState::Topological(iter) => iter.next(),
+            IterState::DepthFirst { stack, depths } => loop {
+                let (node, depth) = stack.pop()?;
+                let first_visit = match depths.entry(P::node_addr(node)) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(depth);
+                        true
+                    }
+                    Entry::Occupied(mut entry) => {
+                        // With a depth limit, a node reached again along a
+                        // shorter path must be expanded again, as some of
+                        // its ancestors may have been out of reach
+                        if self.traversal.max_depth.is_none() || *entry.get() <= depth {
+                            continue;
+                        }
+                        entry.insert(depth);
+                        false
+                    }
+                };
+                if self.traversal.expand(depth) {
+                    let parents = self.traversal.parents(node).collect::<Vec<_>>();
+                    stack.extend(parents.into_iter().rev().map(|p| (p, depth + 1)));
+                }
+                if first_visit {
+                    return Some((node, depth));
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+
+    /// A diamond `a -> (b, c) -> d` with an extra edge `a -> d`.
+    fn diamond() -> RelRc<&'static str, usize> {
+        let a = RelRc::new("a");
+        let b = RelRc::with_parents("b", [(a.clone(), 1)]);
+        let c = RelRc::with_parents("c", [(a.clone(), 1)]);
+        RelRc::with_parents("d", [(b, 1), (c, 2), (a, 3)])
+    }
+
+    #[rstest]
+    #[case(TraversalOrder::BreadthFirst, vec![("d", 0), ("b", 1), ("c", 1), ("a", 1)])]
+    #[case(TraversalOrder::DepthFirst, vec![("d", 0), ("b", 1), ("a", 2), ("c", 1)])]
+    #[case(TraversalOrder::Topological, vec![("a", 1), ("b", 1), ("c", 1), ("d", 0)])]
+    fn test_traversal_orders(
+        #[case] order: TraversalOrder,
+        #[case] expected: Vec<(&'static str, usize)>,
+    ) {
+        let d = diamond();
+        let visited = d
+            .traverse_ancestors()
+            .order(order)
+            .into_iter()
+            .map(|(n, depth)| (*n.value(), depth))
+            .collect_vec();
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn test_max_depth_and_filter() {
+        let d = diamond();
+        let shallow = d
+            .traverse_ancestors()
+            .depth_first()
+            .max_depth(0)
+            .into_iter()
+            .map(|(n, _)| *n.value())
+            .collect_vec();
+        assert_eq!(shallow, ["d"]);
+
+        // `a` is first reached at the maximum depth through `b`, but its
+        // parent `z` is in reach through the edge `a -> d`
+        let z = RelRc::new("z");
+        let a = RelRc::with_parents("a", [(z, 1)]);
+        let b = RelRc::with_parents("b", [(a.clone(), 1)]);
+        let d2 = RelRc::with_parents("d", [(b, 1), (a, 1)]);
+        let limited = d2
+            .traverse_ancestors()
+            .depth_first()
+            .max_depth(2)
+            .into_iter()
+            .map(|(n, depth)| (*n.value(), depth))
+            .collect_vec();
+        assert_eq!(limited, [("d", 0), ("b", 1), ("a", 2), ("z", 2)]);
+
+        // Only follow edges of weight 1: `a` is reached through `b` only
+        let filtered = d
+            .traverse_ancestors()
+            .topological()
+            .filter_edges(|e| *e.value() == 1)
+            .into_iter()
+            .map(|(n, depth)| (*n.value(), depth))
+            .collect_vec();
+        assert_eq!(filtered, [("a", 2), ("b", 1), ("d", 0)]);
+
+        // Diamonds no longer interrupt the traversal
+        assert_eq!(d.all_ancestors().count(), 4);
+    }
+}